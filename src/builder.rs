@@ -0,0 +1,89 @@
+use crate::{platform, Clipboard, Error};
+
+use raw_window_handle::HasDisplayHandle;
+use std::time::Duration;
+
+/// Configures a [`Clipboard`] before connecting to it.
+///
+/// Settings a platform has no use for are ignored.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use window_clipboard::Clipboard;
+/// # fn connect(window: &impl raw_window_handle::HasDisplayHandle) {
+/// let clipboard = unsafe {
+///     Clipboard::builder()
+///         .read_timeout(Duration::from_secs(10))
+///         .max_transfer_size(64 * 1024 * 1024)
+///         .connect(window)
+/// };
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClipboardBuilder {
+    pub(crate) read_timeout: Duration,
+    pub(crate) max_transfer_size: Option<usize>,
+    pub(crate) incr_chunk_size: usize,
+}
+
+impl ClipboardBuilder {
+    /// Sets how long the application owning the clipboard has to send its
+    /// contents before a read fails with [`Error::Timeout`].
+    ///
+    /// Defaults to 3 seconds.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets the largest contents that will be read, in bytes.
+    ///
+    /// Reading larger contents fails with [`Error::TooLarge`]. There is no
+    /// limit by default.
+    pub fn max_transfer_size(mut self, size: usize) -> Self {
+        self.max_transfer_size = Some(size);
+        self
+    }
+
+    /// Sets the size of the chunks large contents are sent to other
+    /// applications in, in bytes.
+    ///
+    /// Only used by X11, where chunks are also limited by the largest request
+    /// the server accepts. Defaults to 4000 bytes.
+    pub fn incr_chunk_size(mut self, size: usize) -> Self {
+        self.incr_chunk_size = size;
+        self
+    }
+
+    /// Connects to the clipboard with these settings.
+    ///
+    /// # Safety
+    /// The display handle must be valid for the lifetime of `Clipboard`.
+    pub unsafe fn connect<W: HasDisplayHandle>(
+        self,
+        window: &W,
+    ) -> Result<Clipboard, Error> {
+        let raw = platform::connect(window, &self)?;
+
+        Ok(Clipboard { raw })
+    }
+
+    /// Connects to the clipboard with these settings, without a window.
+    ///
+    /// See [`Clipboard::connect_headless`].
+    pub fn connect_headless(self) -> Result<Clipboard, Error> {
+        let raw = platform::connect_headless(&self)?;
+
+        Ok(Clipboard { raw })
+    }
+}
+
+impl Default for ClipboardBuilder {
+    fn default() -> Self {
+        ClipboardBuilder {
+            read_timeout: Duration::from_secs(3),
+            max_transfer_size: None,
+            incr_chunk_size: 4000,
+        }
+    }
+}
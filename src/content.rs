@@ -0,0 +1,65 @@
+/// The contents of a clipboard write, offered in one or more formats at once.
+///
+/// Applications that paste can then pick the richest format they understand.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClipboardContent {
+    formats: Vec<(String, Vec<u8>)>,
+}
+
+impl ClipboardContent {
+    /// The MIME type used for plain text.
+    pub const TEXT: &'static str = "text/plain;charset=utf-8";
+
+    /// Creates an empty [`ClipboardContent`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a plain text representation.
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.with(Self::TEXT, text.into())
+    }
+
+    /// Adds an HTML representation.
+    pub fn html(self, html: impl Into<String>) -> Self {
+        self.with(crate::html::MIME_TYPE, html.into())
+    }
+
+    /// Adds a representation with the given MIME type, replacing any
+    /// previous one with the same type.
+    pub fn with(
+        mut self,
+        mime: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        let mime = mime.into();
+
+        self.formats.retain(|(existing, _)| *existing != mime);
+        self.formats.push((mime, data.into()));
+
+        self
+    }
+
+    /// Returns the data of the given MIME type, if present.
+    pub fn get(&self, mime: &str) -> Option<&[u8]> {
+        self.formats
+            .iter()
+            .find(|(existing, _)| existing == mime)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Returns the MIME types of every representation, in insertion order.
+    pub fn mimes(&self) -> impl Iterator<Item = &str> {
+        self.formats.iter().map(|(mime, _)| mime.as_str())
+    }
+
+    /// Returns `true` if there are no representations.
+    pub fn is_empty(&self) -> bool {
+        self.formats.is_empty()
+    }
+
+    /// Consumes the [`ClipboardContent`], returning its `(mime, data)` pairs.
+    pub fn into_formats(self) -> Vec<(String, Vec<u8>)> {
+        self.formats
+    }
+}
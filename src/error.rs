@@ -0,0 +1,56 @@
+use std::error::Error as StdError;
+
+/// An error that occurred while accessing the clipboard.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The clipboard is empty.
+    #[error("the clipboard is empty")]
+    Empty,
+
+    /// The operation is not supported on this platform.
+    #[error("the operation is not supported on this platform")]
+    Unsupported,
+
+    /// The application owning the clipboard did not answer in time.
+    #[error("the clipboard owner did not answer in time")]
+    Timeout,
+
+    /// The clipboard contents are not available in any of the requested
+    /// formats.
+    #[error(
+        "the clipboard contents are not available in the requested format"
+    )]
+    FormatUnavailable,
+
+    /// No clipboard manager is running to keep the clipboard contents
+    /// around once we exit.
+    #[error("no clipboard manager is running")]
+    NoClipboardManager,
+
+    /// The clipboard contents exceed the maximum transfer size.
+    #[error("the clipboard contents exceed the maximum size of {0} bytes")]
+    TooLarge(usize),
+
+    /// The clipboard contents could not be decoded or encoded.
+    #[error("invalid clipboard data: {0}")]
+    InvalidData(#[source] Box<dyn StdError + Send + Sync>),
+
+    /// The platform clipboard failed.
+    #[error("clipboard backend error: {0}")]
+    Backend(#[source] Box<dyn StdError + Send + Sync>),
+}
+
+impl Error {
+    /// Creates an [`Error::InvalidData`] from any error or message.
+    pub fn invalid_data(
+        error: impl Into<Box<dyn StdError + Send + Sync>>,
+    ) -> Self {
+        Error::InvalidData(error.into())
+    }
+
+    /// Creates an [`Error::Backend`] from any error or message.
+    pub fn backend(error: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Error::Backend(error.into())
+    }
+}
@@ -0,0 +1,17 @@
+/// A clipboard selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The clipboard, used by explicit copy and paste.
+    Clipboard,
+    /// The primary selection, holding the latest selected text.
+    Primary,
+}
+
+/// A change of the contents or ownership of a [`Selection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardEvent {
+    /// The selection has new contents, which may have been written by us.
+    Changed(Selection),
+    /// Another application replaced contents we wrote to the selection.
+    OwnershipLost(Selection),
+}
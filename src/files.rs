@@ -0,0 +1,292 @@
+use crate::{ClipboardContent, Error};
+
+use std::path::{Path, PathBuf};
+
+/// What should happen to copied files once they are pasted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileOperation {
+    /// The files are copied, leaving the originals in place.
+    Copy,
+    /// The files are moved.
+    Cut,
+}
+
+/// The standard list of URIs, one per line.
+const URI_LIST: &str = "text/uri-list";
+
+/// The list used by GNOME, prefixed with the operation.
+const GNOME_COPIED_FILES: &str = "x-special/gnome-copied-files";
+
+/// The marker used by KDE to tell cut from copied files.
+const KDE_CUT_SELECTION: &str = "application/x-kde-cutselection";
+
+/// The MIME types file lists are read from, in order of preference.
+pub const MIME_TYPES: [&str; 2] = [GNOME_COPIED_FILES, URI_LIST];
+
+/// Encodes a list of files in every format file managers understand.
+///
+/// Relative paths are resolved against the current directory, since URIs
+/// can only name absolute ones.
+pub fn encode(
+    paths: &[PathBuf],
+    operation: FileOperation,
+) -> Result<ClipboardContent, Error> {
+    let paths = paths
+        .iter()
+        .map(std::path::absolute)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::invalid_data)?;
+
+    let uris: Vec<String> = paths.iter().map(|path| to_uri(path)).collect();
+
+    let gnome_operation = match operation {
+        FileOperation::Copy => "copy",
+        FileOperation::Cut => "cut",
+    };
+
+    let kde_cut = match operation {
+        FileOperation::Copy => "0",
+        FileOperation::Cut => "1",
+    };
+
+    let text: Vec<String> = paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+
+    Ok(ClipboardContent::new()
+        .with(
+            URI_LIST,
+            uris.iter()
+                .map(|uri| format!("{}\r\n", uri))
+                .collect::<String>(),
+        )
+        .with(
+            GNOME_COPIED_FILES,
+            std::iter::once(gnome_operation)
+                .chain(uris.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+        .with(KDE_CUT_SELECTION, kde_cut)
+        .text(text.join("\n")))
+}
+
+/// Decodes a list of files read as the given MIME type.
+///
+/// URIs that do not point to local files are skipped.
+pub fn decode(mime: &str, data: &[u8]) -> Result<Vec<PathBuf>, Error> {
+    let list = std::str::from_utf8(data).map_err(Error::invalid_data)?;
+
+    let mut lines = list.lines();
+
+    // The first line of a GNOME list is the operation.
+    if mime == GNOME_COPIED_FILES {
+        let _ = lines.next();
+    }
+
+    Ok(lines
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(from_uri)
+        .collect())
+}
+
+fn to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+fn from_uri(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file:")?;
+
+    // The authority is optional, but only the local host is supported.
+    let path = match rest.strip_prefix("//") {
+        Some(rest) => {
+            let start = rest.find('/')?;
+
+            match &rest[..start] {
+                "" | "localhost" => &rest[start..],
+                _ => return None,
+            }
+        }
+        None => rest,
+    };
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let high = iter.next()?;
+            let low = iter.next()?;
+            let hex = [high, low];
+
+            bytes.push(
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?,
+            );
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    Some(path_from_bytes(bytes))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    if path.starts_with('/') {
+        path.into_bytes()
+    } else {
+        format!("/{}", path).into_bytes()
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    let path = String::from_utf8_lossy(&bytes);
+
+    PathBuf::from(path.strip_prefix('/').unwrap_or(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(content: &ClipboardContent) -> Vec<Vec<PathBuf>> {
+        [URI_LIST, GNOME_COPIED_FILES]
+            .iter()
+            .map(|mime| decode(mime, content.get(mime).unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let paths = vec![
+            PathBuf::from("/tmp/with space/file.txt"),
+            PathBuf::from("/tmp/100%/#hash?.txt"),
+            PathBuf::from("/tmp/ünïcödé"),
+        ];
+
+        let content = encode(&paths, FileOperation::Copy).unwrap();
+
+        for decoded in decode_all(&content) {
+            assert_eq!(decoded, paths);
+        }
+
+        let uris = std::str::from_utf8(content.get(URI_LIST).unwrap()).unwrap();
+        assert!(uris.starts_with("file:///tmp/with%20space/file.txt\r\n"));
+        assert!(uris.contains("file:///tmp/100%25/%23hash%3F.txt\r\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip_non_utf8() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let paths =
+            vec![PathBuf::from(OsString::from_vec(b"/tmp/\xff\xfe".to_vec()))];
+
+        let content = encode(&paths, FileOperation::Cut).unwrap();
+
+        for decoded in decode_all(&content) {
+            assert_eq!(decoded, paths);
+        }
+
+        assert_eq!(content.get(URI_LIST), Some(&b"file:///tmp/%FF%FE\r\n"[..]));
+    }
+
+    #[test]
+    fn relative_paths_are_made_absolute() {
+        let content =
+            encode(&[PathBuf::from("foo/bar")], FileOperation::Copy).unwrap();
+
+        let expected = vec![std::env::current_dir().unwrap().join("foo/bar")];
+
+        for decoded in decode_all(&content) {
+            assert_eq!(decoded, expected);
+        }
+    }
+
+    #[test]
+    fn gnome_operation() {
+        let paths = [PathBuf::from("/a"), PathBuf::from("/b")];
+
+        let copied = encode(&paths, FileOperation::Copy).unwrap();
+        assert_eq!(
+            copied.get(GNOME_COPIED_FILES),
+            Some(&b"copy\nfile:///a\nfile:///b"[..])
+        );
+        assert_eq!(copied.get(KDE_CUT_SELECTION), Some(&b"0"[..]));
+
+        let cut = encode(&paths, FileOperation::Cut).unwrap();
+        assert_eq!(
+            cut.get(GNOME_COPIED_FILES),
+            Some(&b"cut\nfile:///a\nfile:///b"[..])
+        );
+        assert_eq!(cut.get(KDE_CUT_SELECTION), Some(&b"1"[..]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uri_forms() {
+        let list = b"file:///a\r\nfile://localhost/b\r\nfile:/c\r\n\
+                     file://example.com/d\r\nhttp://example.com/e\r\n";
+
+        assert_eq!(
+            decode(URI_LIST, list).unwrap(),
+            [
+                PathBuf::from("/a"),
+                PathBuf::from("/b"),
+                PathBuf::from("/c")
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let list = b"# a comment\r\n\r\nfile:///a\r\n  # indented\r\n";
+
+        let decoded = decode(URI_LIST, list).unwrap();
+        assert_eq!(decoded.len(), 1);
+    }
+
+    #[test]
+    fn invalid_escapes_are_skipped() {
+        let list = b"file:///a%2\r\nfile:///b%zz\r\nfile:///c\r\n";
+
+        assert_eq!(decode(URI_LIST, list).unwrap().len(), 1);
+    }
+}
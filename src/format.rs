@@ -0,0 +1,12 @@
+/// A format the clipboard contents are currently available in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// The MIME type of the format.
+    ///
+    /// On X11, this is the name of the target atom, which may also be a
+    /// legacy name like `UTF8_STRING`.
+    pub mime: String,
+
+    /// The size of the data in bytes, if the owner provides it.
+    pub size_hint: Option<usize>,
+}
@@ -0,0 +1,663 @@
+use crate::{Clipboard, ClipboardContent, ClipboardEvent, Error, Selection};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Records the contents of the clipboard every time they change, whether
+/// we or other applications changed them.
+///
+/// Changes are only picked up by [`ClipboardHistory::update`], which
+/// should be called regularly, for instance from the event loop of the
+/// application. Writes should go through
+/// [`ClipboardHistory::clipboard_mut`] so they are recorded as well.
+pub struct ClipboardHistory {
+    clipboard: Clipboard,
+    events: mpsc::Receiver<ClipboardEvent>,
+    /// The entries, newest first.
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    next_id: u64,
+}
+
+/// Identifies a [`HistoryEntry`] within its [`ClipboardHistory`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryId(u64);
+
+/// The contents of the clipboard at some point in time.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    id: EntryId,
+    content: ClipboardContent,
+    time: SystemTime,
+    is_pinned: bool,
+    hash: u64,
+}
+
+impl ClipboardHistory {
+    /// The version of the format written by [`ClipboardHistory::save`].
+    const VERSION: u32 = 1;
+
+    /// The bytes every saved history starts with.
+    const MAGIC: &'static [u8; 4] = b"WCBH";
+
+    /// Starts recording the changes of `clipboard`, keeping at most
+    /// `capacity` entries besides pinned ones.
+    ///
+    /// The current contents are recorded right away. Fails if the platform
+    /// cannot notify us of changes.
+    pub fn new(clipboard: Clipboard, capacity: usize) -> Result<Self, Error> {
+        let events = clipboard.subscribe()?;
+
+        let mut history = ClipboardHistory {
+            clipboard,
+            events,
+            entries: VecDeque::new(),
+            capacity,
+            next_id: 0,
+        };
+
+        history.record()?;
+
+        Ok(history)
+    }
+
+    /// Returns the recorded [`Clipboard`].
+    pub fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    /// Returns the recorded [`Clipboard`], to write to it.
+    pub fn clipboard_mut(&mut self) -> &mut Clipboard {
+        &mut self.clipboard
+    }
+
+    /// Records the clipboard contents if they changed since the last call.
+    ///
+    /// Every available MIME type is read. Returns `true` if an entry was
+    /// added or moved to the front.
+    pub fn update(&mut self) -> Result<bool, Error> {
+        let mut changed = false;
+
+        loop {
+            match self.events.try_recv() {
+                Ok(ClipboardEvent::Changed(Selection::Clipboard)) => {
+                    changed = true;
+                }
+                Ok(_) => {}
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Err(Error::backend("clipboard events stopped"));
+                }
+            }
+        }
+
+        if changed {
+            self.record()
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns the entries, newest first.
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the entry with the given id, if it is still recorded.
+    pub fn get(&self, id: EntryId) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Returns the maximum amount of entries kept besides pinned ones.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the maximum amount of entries kept besides pinned ones,
+    /// dropping the oldest ones if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    /// Pins or unpins an entry. Pinned entries are never dropped to make
+    /// room for new ones.
+    ///
+    /// Returns `false` if there is no such entry.
+    pub fn pin(&mut self, id: EntryId, is_pinned: bool) -> bool {
+        let found = match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.is_pinned = is_pinned;
+                true
+            }
+            None => false,
+        };
+
+        self.truncate();
+
+        found
+    }
+
+    /// Removes an entry, returning it.
+    pub fn remove(&mut self, id: EntryId) -> Option<HistoryEntry> {
+        let index = self.entries.iter().position(|entry| entry.id == id)?;
+
+        self.entries.remove(index)
+    }
+
+    /// Removes every entry that is not pinned.
+    pub fn clear(&mut self) {
+        self.entries.retain(|entry| entry.is_pinned);
+    }
+
+    /// Writes an entry back to the clipboard, moving it to the front.
+    pub fn restore(&mut self, id: EntryId) -> Result<(), Error> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or(Error::Empty)?;
+
+        let entry = &self.entries[index];
+
+        match self.clipboard.write_content(entry.content.clone()) {
+            // Some platforms can only write text.
+            Err(Error::Unsupported) => {
+                let text = entry.text().ok_or(Error::Unsupported)?;
+                self.clipboard.write(text.to_owned())?;
+            }
+            result => result?,
+        }
+
+        if let Some(mut entry) = self.entries.remove(index) {
+            entry.time = SystemTime::now();
+            self.entries.push_front(entry);
+        }
+
+        Ok(())
+    }
+
+    /// Saves the entries to the file at `path`, replacing it.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+
+        let mut data = Vec::new();
+        self.encode(&mut data).map_err(Error::backend)?;
+
+        // Renaming keeps the previous history intact if writing fails.
+        fs::write(&temporary, data)
+            .and_then(|()| fs::rename(&temporary, path))
+            .map_err(Error::backend)
+    }
+
+    /// Replaces the entries with the ones saved at `path` by
+    /// [`ClipboardHistory::save`].
+    ///
+    /// Fails with [`Error::InvalidData`] if the file is not a saved history,
+    /// or was saved by a newer version.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let data = fs::read(path).map_err(Error::backend)?;
+        let entries =
+            Self::decode(&mut data.as_slice()).map_err(Error::invalid_data)?;
+
+        self.next_id = entries
+            .iter()
+            .map(|entry| entry.id.0 + 1)
+            .max()
+            .unwrap_or(0)
+            .max(self.next_id);
+
+        self.entries = entries.into();
+        self.truncate();
+
+        Ok(())
+    }
+
+    /// Reads the current clipboard contents and records them.
+    fn record(&mut self) -> Result<bool, Error> {
+        let content = match self.read_content() {
+            Ok(content) => content,
+            Err(Error::Empty) => return Ok(false),
+            Err(error) => return Err(error),
+        };
+
+        if content.is_empty() {
+            return Ok(false);
+        }
+
+        let hash = hash(&content);
+
+        // The hash only rules entries out quickly, as it may collide.
+        match self
+            .entries
+            .iter()
+            .position(|entry| entry.hash == hash && entry.content == content)
+        {
+            Some(index) => {
+                if let Some(mut entry) = self.entries.remove(index) {
+                    entry.time = SystemTime::now();
+                    self.entries.push_front(entry);
+                }
+            }
+            None => {
+                let id = EntryId(self.next_id);
+                self.next_id += 1;
+
+                self.entries.push_front(HistoryEntry {
+                    id,
+                    content,
+                    time: SystemTime::now(),
+                    is_pinned: false,
+                    hash,
+                });
+            }
+        }
+
+        self.truncate();
+
+        Ok(true)
+    }
+
+    /// Reads every MIME type the clipboard contents are available in.
+    fn read_content(&self) -> Result<ClipboardContent, Error> {
+        // Some platforms can only tell us about text.
+        let formats = match self.clipboard.available_formats() {
+            Ok(formats) => formats,
+            Err(Error::Unsupported) => Vec::new(),
+            Err(error) => return Err(error),
+        };
+
+        let mut content = ClipboardContent::new();
+
+        // Legacy X11 targets like `UTF8_STRING` only alias MIME types, and
+        // text is read once below whatever its encodings, so that the same
+        // contents always hash the same.
+        let formats = formats.iter().filter(|format| {
+            format.mime.contains('/') && !format.mime.starts_with("text/plain")
+        });
+
+        for format in formats {
+            match self.clipboard.read_mime(&[&format.mime]) {
+                Ok((mime, data)) => content = content.with(mime, data),
+                Err(Error::FormatUnavailable) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        match self.clipboard.read() {
+            Ok(text) => content = content.text(text),
+            Err(Error::FormatUnavailable) => {}
+            Err(Error::Empty) if !content.is_empty() => {}
+            Err(error) => return Err(error),
+        }
+
+        Ok(content)
+    }
+
+    /// Drops the oldest unpinned entries over the capacity.
+    fn truncate(&mut self) {
+        let mut unpinned = 0;
+
+        self.entries.retain(|entry| {
+            if entry.is_pinned {
+                return true;
+            }
+
+            unpinned += 1;
+            unpinned <= self.capacity
+        });
+    }
+
+    fn encode(&self, output: &mut impl Write) -> io::Result<()> {
+        output.write_all(Self::MAGIC)?;
+        output.write_all(&Self::VERSION.to_le_bytes())?;
+        write_len(output, self.entries.len())?;
+
+        for entry in &self.entries {
+            let time =
+                entry.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+            output.write_all(&entry.id.0.to_le_bytes())?;
+            output.write_all(&time.as_secs().to_le_bytes())?;
+            output.write_all(&time.subsec_nanos().to_le_bytes())?;
+            output.write_all(&[u8::from(entry.is_pinned)])?;
+
+            let formats: Vec<_> = entry.content.mimes().collect();
+            write_len(output, formats.len())?;
+
+            for mime in formats {
+                let data = entry.content.get(mime).unwrap_or_default();
+
+                write_len(output, mime.len())?;
+                output.write_all(mime.as_bytes())?;
+                write_len(output, data.len())?;
+                output.write_all(data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode(input: &mut impl Read) -> io::Result<Vec<HistoryEntry>> {
+        let invalid =
+            |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        if &read_array::<4>(input)? != Self::MAGIC {
+            return Err(invalid("not a clipboard history"));
+        }
+
+        if u32::from_le_bytes(read_array(input)?) > Self::VERSION {
+            return Err(invalid("unsupported clipboard history version"));
+        }
+
+        let count = read_len(input)?;
+        let mut entries = Vec::new();
+
+        for _ in 0..count {
+            let id = EntryId(u64::from_le_bytes(read_array(input)?));
+            let seconds = u64::from_le_bytes(read_array(input)?);
+            let nanos = u32::from_le_bytes(read_array(input)?);
+            let [is_pinned] = read_array(input)?;
+
+            let mut content = ClipboardContent::new();
+
+            for _ in 0..read_len(input)? {
+                let mime = String::from_utf8(read_bytes(input)?)
+                    .map_err(|_| invalid("invalid MIME type"))?;
+
+                content = content.with(mime, read_bytes(input)?);
+            }
+
+            // `Duration::new` carries extra nanoseconds over to seconds,
+            // which may overflow.
+            if nanos >= 1_000_000_000 {
+                return Err(invalid("invalid entry time"));
+            }
+
+            let time = UNIX_EPOCH
+                .checked_add(Duration::new(seconds, nanos))
+                .ok_or_else(|| invalid("invalid entry time"))?;
+
+            entries.push(HistoryEntry {
+                id,
+                hash: hash(&content),
+                content,
+                time,
+                is_pinned: is_pinned != 0,
+            });
+        }
+
+        Ok(entries)
+    }
+}
+
+impl HistoryEntry {
+    /// Returns the id of the entry.
+    pub fn id(&self) -> EntryId {
+        self.id
+    }
+
+    /// Returns every format the contents were recorded in.
+    pub fn content(&self) -> &ClipboardContent {
+        &self.content
+    }
+
+    /// Returns the plain text representation, if any.
+    pub fn text(&self) -> Option<&str> {
+        self.content
+            .get(ClipboardContent::TEXT)
+            .and_then(|text| std::str::from_utf8(text).ok())
+    }
+
+    /// Returns when the contents were last copied.
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// Returns `true` if the entry is pinned.
+    pub fn is_pinned(&self) -> bool {
+        self.is_pinned
+    }
+}
+
+/// Hashes every format of `content`, regardless of their order.
+fn hash(content: &ClipboardContent) -> u64 {
+    let mut formats: Vec<_> = content
+        .mimes()
+        .map(|mime| (mime, content.get(mime).unwrap_or_default()))
+        .collect();
+
+    formats.sort_unstable();
+
+    let mut hasher = DefaultHasher::new();
+    formats.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn write_len(output: &mut impl Write, len: usize) -> io::Result<()> {
+    output.write_all(&(len as u64).to_le_bytes())
+}
+
+fn read_len(input: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(u64::from_le_bytes(read_array(input)?))
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut array = [0; N];
+    input.read_exact(&mut array)?;
+
+    Ok(array)
+}
+
+fn read_bytes(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_len(input)?;
+    let mut bytes = Vec::new();
+
+    // The length is not trusted to preallocate, in case it is corrupted.
+    let read = input.take(len as u64).read_to_end(&mut bytes)?;
+
+    if read == len {
+        Ok(bytes)
+    } else {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryClipboard;
+
+    fn history(capacity: usize) -> (MemoryClipboard, ClipboardHistory) {
+        let memory = MemoryClipboard::new();
+        let clipboard = Clipboard::from_provider(Box::new(memory.clone()));
+        let history = ClipboardHistory::new(clipboard, capacity).unwrap();
+
+        (memory, history)
+    }
+
+    fn copy(
+        memory: &MemoryClipboard,
+        history: &mut ClipboardHistory,
+        content: ClipboardContent,
+    ) {
+        memory.set_contents(Selection::Clipboard, content);
+        assert!(history.update().unwrap());
+    }
+
+    fn texts(history: &ClipboardHistory) -> Vec<&str> {
+        history.entries().filter_map(HistoryEntry::text).collect()
+    }
+
+    fn encode(history: &ClipboardHistory) -> Vec<u8> {
+        let mut data = Vec::new();
+        history.encode(&mut data).unwrap();
+
+        data
+    }
+
+    fn decode(data: &[u8]) -> io::Result<Vec<HistoryEntry>> {
+        ClipboardHistory::decode(&mut &data[..])
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let (memory, mut history) = history(10);
+
+        copy(&memory, &mut history, ClipboardContent::new().text("first"));
+        copy(
+            &memory,
+            &mut history,
+            ClipboardContent::new()
+                .text("second")
+                .with("image/png", vec![0, 1, 2, 255]),
+        );
+
+        let first = history.entries().last().unwrap().id();
+        assert!(history.pin(first, true));
+
+        let path = std::env::temp_dir()
+            .join(format!("window_clipboard-history-{}", std::process::id()));
+
+        history.save(&path).unwrap();
+
+        let (memory, mut loaded) = self::history(10);
+        let result = loaded.load(&path);
+        let _ = fs::remove_file(&path);
+        result.unwrap();
+
+        let original: Vec<_> = history.entries().collect();
+        let restored: Vec<_> = loaded.entries().collect();
+
+        assert_eq!(original.len(), restored.len());
+
+        for (original, restored) in original.iter().zip(&restored) {
+            assert_eq!(original.id(), restored.id());
+            assert_eq!(original.content(), restored.content());
+            assert_eq!(original.time(), restored.time());
+            assert_eq!(original.is_pinned(), restored.is_pinned());
+        }
+
+        // New entries do not reuse the ids of loaded ones.
+        copy(&memory, &mut loaded, ClipboardContent::new().text("third"));
+
+        let ids: Vec<_> = loaded.entries().map(HistoryEntry::id).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids[1..].contains(&ids[0]));
+    }
+
+    #[test]
+    fn load_rejects_other_files_and_newer_versions() {
+        let (memory, mut history) = history(10);
+        copy(&memory, &mut history, ClipboardContent::new().text("text"));
+
+        let data = encode(&history);
+        assert_eq!(decode(&data).unwrap().len(), 1);
+
+        let mut magic = data.clone();
+        magic[0] = b'X';
+        let error = decode(&magic).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut version = data;
+        version[4..8]
+            .copy_from_slice(&(ClipboardHistory::VERSION + 1).to_le_bytes());
+        let error = decode(&version).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn load_rejects_truncated_data() {
+        let (memory, mut history) = history(10);
+        copy(
+            &memory,
+            &mut history,
+            ClipboardContent::new().text("text").html("<b>text</b>"),
+        );
+
+        let data = encode(&history);
+
+        for len in 0..data.len() {
+            assert!(decode(&data[..len]).is_err(), "accepted {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn load_rejects_invalid_times() {
+        let (memory, mut history) = history(10);
+        copy(&memory, &mut history, ClipboardContent::new().text("text"));
+
+        // The time follows the magic, version, entry count and id.
+        let seconds = 4 + 4 + 8 + 8;
+        let nanos = seconds + 8;
+
+        let mut data = encode(&history);
+        data[nanos..nanos + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = decode(&data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut data = encode(&history);
+        data[seconds..seconds + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = decode(&data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn copying_again_moves_the_entry_to_the_front() {
+        let (memory, mut history) = history(10);
+
+        copy(&memory, &mut history, ClipboardContent::new().text("a"));
+        copy(&memory, &mut history, ClipboardContent::new().text("b"));
+
+        let id = history.entries().last().unwrap().id();
+
+        copy(&memory, &mut history, ClipboardContent::new().text("a"));
+
+        assert_eq!(texts(&history), ["a", "b"]);
+        assert_eq!(history.entries().next().unwrap().id(), id);
+    }
+
+    #[test]
+    fn hash_collisions_are_distinct_entries() {
+        let (memory, mut history) = history(10);
+        let b = ClipboardContent::new().text("b");
+
+        copy(&memory, &mut history, ClipboardContent::new().text("a"));
+
+        // Pretend that "a" and "b" collide.
+        history.entries[0].hash = hash(&b);
+
+        copy(&memory, &mut history, b);
+
+        assert_eq!(texts(&history), ["b", "a"]);
+    }
+
+    #[test]
+    fn pinned_entries_outlive_the_capacity() {
+        let (memory, mut history) = history(10);
+
+        for text in ["a", "b", "c"] {
+            copy(&memory, &mut history, ClipboardContent::new().text(text));
+        }
+
+        let b = history.entries().nth(1).unwrap().id();
+        assert!(history.pin(b, true));
+
+        history.set_capacity(0);
+        assert_eq!(texts(&history), ["b"]);
+
+        history.clear();
+        assert_eq!(texts(&history), ["b"]);
+
+        assert!(history.pin(b, false));
+        assert!(texts(&history).is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::Error;
+
+/// The MIME type of HTML documents.
+pub const MIME_TYPE: &str = "text/html";
+
+/// Decodes HTML read from the clipboard.
+///
+/// Some applications (like Firefox on X11) offer HTML as UTF-16 with a
+/// leading byte order mark, while most use UTF-8.
+pub fn decode(data: Vec<u8>) -> Result<String, Error> {
+    match data.as_slice() {
+        [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => {
+            String::from_utf8(rest.to_vec()).map_err(Error::invalid_data)
+        }
+        _ => String::from_utf8(data).map_err(Error::invalid_data),
+    }
+}
+
+/// Decodes UTF-16, reading each code unit with `from`.
+fn decode_utf16(
+    data: &[u8],
+    from: fn([u8; 2]) -> u16,
+) -> Result<String, Error> {
+    let units = data.chunks_exact(2);
+
+    if !units.remainder().is_empty() {
+        return Err(Error::invalid_data("UTF-16 of an odd length"));
+    }
+
+    char::decode_utf16(units.map(|unit| from([unit[0], unit[1]])))
+        .collect::<Result<String, _>>()
+        .map_err(Error::invalid_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &str = "<b>Héllo 👋</b>";
+
+    fn utf16(bom: u16, to: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        std::iter::once(bom)
+            .chain(HTML.encode_utf16())
+            .flat_map(to)
+            .collect()
+    }
+
+    #[test]
+    fn utf8() {
+        assert_eq!(decode(HTML.as_bytes().to_vec()).unwrap(), HTML);
+
+        let mut data = vec![0xEF, 0xBB, 0xBF];
+        data.extend_from_slice(HTML.as_bytes());
+        assert_eq!(decode(data).unwrap(), HTML);
+    }
+
+    #[test]
+    fn utf16_little_endian() {
+        let data = utf16(0xFEFF, u16::to_le_bytes);
+
+        assert_eq!(&data[..2], [0xFF, 0xFE]);
+        assert_eq!(decode(data).unwrap(), HTML);
+    }
+
+    #[test]
+    fn utf16_big_endian() {
+        let data = utf16(0xFEFF, u16::to_be_bytes);
+
+        assert_eq!(&data[..2], [0xFE, 0xFF]);
+        assert_eq!(decode(data).unwrap(), HTML);
+    }
+
+    #[test]
+    fn malformed_data_is_invalid() {
+        let mut odd = utf16(0xFEFF, u16::to_le_bytes);
+        odd.push(b'>');
+
+        // A lone high surrogate.
+        let surrogate = vec![0xFF, 0xFE, 0x3D, 0xD8];
+
+        for data in [odd, surrogate, vec![0xEF, 0xBB, 0xBF, 0xFF], vec![0xC3]] {
+            assert!(matches!(decode(data), Err(Error::InvalidData(_))));
+        }
+    }
+}
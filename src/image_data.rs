@@ -0,0 +1,134 @@
+use crate::Error;
+
+use image::{ImageFormat, RgbaImage};
+
+use std::io::Cursor;
+
+/// An image with 8-bit RGBA pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    /// The width of the image, in pixels.
+    pub width: usize,
+    /// The height of the image, in pixels.
+    pub height: usize,
+    /// The pixels of the image, row by row, with 4 bytes per pixel.
+    pub rgba: Vec<u8>,
+}
+
+impl ImageData {
+    /// The MIME types images are read from, in order of preference.
+    pub(crate) const MIME_TYPES: [&'static str; 3] =
+        ["image/png", "image/bmp", "image/jpeg"];
+
+    /// The MIME type images are written as.
+    pub(crate) const PNG: &'static str = "image/png";
+
+    /// Encodes the image as a PNG file.
+    pub(crate) fn into_png(self) -> Result<Vec<u8>, Error> {
+        let width = u32::try_from(self.width).map_err(Error::invalid_data)?;
+        let height = u32::try_from(self.height).map_err(Error::invalid_data)?;
+
+        let image =
+            RgbaImage::from_raw(width, height, self.rgba).ok_or_else(|| {
+                Error::invalid_data("image size does not match its dimensions")
+            })?;
+
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(Error::invalid_data)?;
+
+        Ok(png)
+    }
+
+    /// Decodes an image file of the given MIME type.
+    pub(crate) fn decode(mime: &str, data: &[u8]) -> Result<Self, Error> {
+        let format = ImageFormat::from_mime_type(mime).ok_or_else(|| {
+            Error::invalid_data(format!("unsupported image type: {}", mime))
+        })?;
+
+        let image = image::load_from_memory_with_format(data, format)
+            .map_err(Error::invalid_data)?
+            .into_rgba8();
+
+        Ok(ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            rgba: image.into_raw(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+
+    use image::{DynamicImage, Rgba};
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255])
+        })
+    }
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+
+        data
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let image = ImageData {
+            width: 3,
+            height: 2,
+            rgba: gradient(3, 2).into_raw(),
+        };
+
+        let png = image.clone().into_png().unwrap();
+        assert_eq!(ImageData::decode(ImageData::PNG, &png).unwrap(), image);
+    }
+
+    #[test]
+    fn bmp_and_jpeg_are_decoded() {
+        let image = DynamicImage::ImageRgba8(gradient(8, 4));
+
+        let bmp = encode(image.clone(), ImageFormat::Bmp);
+        let decoded = ImageData::decode("image/bmp", &bmp).unwrap();
+        assert_eq!(decoded.rgba, image.to_rgba8().into_raw());
+
+        // JPEG is lossy, and has no alpha channel.
+        let jpeg =
+            encode(DynamicImage::ImageRgb8(image.to_rgb8()), ImageFormat::Jpeg);
+        let decoded = ImageData::decode("image/jpeg", &jpeg).unwrap();
+        assert_eq!((decoded.width, decoded.height), (8, 4));
+        assert_eq!(decoded.rgba.len(), 8 * 4 * 4);
+    }
+
+    #[test]
+    fn invalid_images_are_rejected() {
+        let image = ImageData {
+            width: 3,
+            height: 2,
+            rgba: vec![0; 4],
+        };
+        assert!(matches!(image.into_png(), Err(Error::InvalidData(_))));
+
+        let image = ImageData {
+            width: usize::MAX,
+            height: 1,
+            rgba: Vec::new(),
+        };
+        assert!(matches!(image.into_png(), Err(Error::InvalidData(_))));
+
+        assert!(matches!(
+            ImageData::decode("image/png", b"not a png"),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(
+            ImageData::decode("image/x-unknown", b""),
+            Err(Error::InvalidData(_))
+        ));
+    }
+}
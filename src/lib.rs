@@ -46,52 +46,373 @@ mod platform;
 #[path = "platform/dummy.rs"]
 mod platform;
 
+mod builder;
+mod content;
+mod error;
+mod event;
+mod files;
+mod format;
+mod history;
+mod html;
+#[cfg(feature = "image")]
+mod image_data;
+mod provider;
+
+pub mod memory;
+
+pub use builder::ClipboardBuilder;
+pub use content::ClipboardContent;
+pub use error::Error;
+pub use event::{ClipboardEvent, Selection};
+pub use files::FileOperation;
+pub use format::Format;
+pub use history::{ClipboardHistory, EntryId, HistoryEntry};
+#[cfg(feature = "image")]
+pub use image_data::ImageData;
+pub use provider::{cached, DataProvider};
+
 use raw_window_handle::HasDisplayHandle;
-use std::error::Error;
+use std::future::{self, Future};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::mpsc;
+
+/// A future returned by the asynchronous methods of a [`Clipboard`].
+///
+/// It is driven by the platform in the background and can be awaited on any
+/// executor. Dropping it cancels the operation.
+pub type ClipboardFuture<T> =
+    Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>;
 
 pub struct Clipboard {
     raw: Box<dyn ClipboardProvider>,
 }
 
 impl Clipboard {
-    /// Safety: the display handle must be valid for the lifetime of `Clipboard`
+    /// Connects to the clipboard with the default settings.
+    ///
+    /// # Safety
+    /// The display handle must be valid for the lifetime of `Clipboard`.
     pub unsafe fn connect<W: HasDisplayHandle>(
         window: &W,
-    ) -> Result<Self, Box<dyn Error>> {
-        let raw = platform::connect(window)?;
+    ) -> Result<Self, Error> {
+        Self::builder().connect(window)
+    }
 
-        Ok(Clipboard { raw })
+    /// Connects to the clipboard with the default settings, without a window.
+    ///
+    /// Useful for command-line tools and services. On Linux, the Wayland
+    /// compositor named by `$WAYLAND_DISPLAY` is used if it supports data
+    /// control, since it only allows access to the clipboard without
+    /// keyboard focus then. Otherwise, the X11 server named by `$DISPLAY` is
+    /// preferred, and the compositor is only used as a last resort.
+    ///
+    /// Fails with [`Error::Unsupported`] if no clipboard can be reached.
+    pub fn connect_headless() -> Result<Self, Error> {
+        Self::builder().connect_headless()
     }
 
-    pub fn read(&self) -> Result<String, Box<dyn Error>> {
+    /// Creates a [`Clipboard`] backed by the given [`ClipboardProvider`]
+    /// instead of the platform clipboard.
+    ///
+    /// See [`memory::MemoryClipboard`] for a provider to test with.
+    pub fn from_provider(provider: Box<dyn ClipboardProvider>) -> Self {
+        Clipboard { raw: provider }
+    }
+
+    /// Creates a [`ClipboardBuilder`] to configure a connection.
+    pub fn builder() -> ClipboardBuilder {
+        ClipboardBuilder::default()
+    }
+
+    pub fn read(&self) -> Result<String, Error> {
         self.raw.read()
     }
 
-    pub fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
+    pub fn write(&mut self, contents: String) -> Result<(), Error> {
         self.raw.write(contents)
     }
+
+    /// Reads the clipboard on behalf of the input event with the given
+    /// timestamp or serial.
+    ///
+    /// Using the time of the event that caused the read avoids races with
+    /// other applications on X11. Elsewhere, this is the same as
+    /// [`Clipboard::read`].
+    pub fn read_at(&self, time: u32) -> Result<String, Error> {
+        self.raw.read_at(time)
+    }
+
+    /// Writes to the clipboard on behalf of the input event with the given
+    /// timestamp (X11) or serial (Wayland).
+    ///
+    /// Servers may ignore writes that are not tied to a recent input event,
+    /// so this is more reliable than [`Clipboard::write`] when the event is
+    /// known. Elsewhere, this is the same as [`Clipboard::write`].
+    pub fn write_at(
+        &mut self,
+        contents: String,
+        time: u32,
+    ) -> Result<(), Error> {
+        self.raw.write_at(contents, time)
+    }
+
+    /// Reads the clipboard without blocking the current thread.
+    pub fn read_async(&self) -> ClipboardFuture<String> {
+        self.raw.read_async()
+    }
+
+    /// Writes to the clipboard, returning a future that completes once the
+    /// contents are offered to other applications.
+    pub fn write_async(&mut self, contents: String) -> ClipboardFuture<()> {
+        self.raw.write_async(contents)
+    }
+
+    /// Reads the clipboard contents as the first of the given MIME types
+    /// that is available, in order of preference.
+    ///
+    /// Returns the chosen MIME type along with the raw data.
+    pub fn read_mime(
+        &self,
+        mimes: &[&str],
+    ) -> Result<(String, Vec<u8>), Error> {
+        self.raw.read_mime(mimes)
+    }
+
+    /// Like [`Clipboard::read_mime`], but without blocking the current
+    /// thread.
+    pub fn read_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> ClipboardFuture<(String, Vec<u8>)> {
+        self.raw.read_mime_async(mimes)
+    }
+
+    /// Reads an image from the clipboard, decoding it to RGBA.
+    ///
+    /// PNG is preferred, but BMP and JPEG are accepted as well.
+    #[cfg(feature = "image")]
+    pub fn read_image(&self) -> Result<ImageData, Error> {
+        let (mime, data) = self.raw.read_mime(&ImageData::MIME_TYPES)?;
+
+        ImageData::decode(&mime, &data)
+    }
+
+    /// Writes an image to the clipboard, encoded as PNG.
+    #[cfg(feature = "image")]
+    pub fn write_image(&mut self, image: ImageData) -> Result<(), Error> {
+        let png = image.into_png()?;
+
+        self.raw
+            .write_content(ClipboardContent::new().with(ImageData::PNG, png))
+    }
+
+    /// Reads HTML from the clipboard.
+    pub fn read_html(&self) -> Result<String, Error> {
+        let (_, data) = self.raw.read_mime(&[html::MIME_TYPE])?;
+
+        html::decode(data)
+    }
+
+    /// Writes HTML to the clipboard, along with a plain text alternative for
+    /// applications that do not understand it.
+    pub fn write_html(
+        &mut self,
+        html: impl Into<String>,
+        alt_text: impl Into<String>,
+    ) -> Result<(), Error> {
+        self.raw
+            .write_content(ClipboardContent::new().html(html).text(alt_text))
+    }
+
+    /// Reads a list of files from the clipboard.
+    pub fn read_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let (mime, data) = self.raw.read_mime(&files::MIME_TYPES)?;
+
+        files::decode(&mime, &data)
+    }
+
+    /// Writes a list of files to the clipboard, to be copied or moved by the
+    /// application they are pasted in.
+    ///
+    /// Relative paths are resolved against the current directory.
+    pub fn write_files(
+        &mut self,
+        paths: &[PathBuf],
+        operation: FileOperation,
+    ) -> Result<(), Error> {
+        self.raw.write_content(files::encode(paths, operation)?)
+    }
+
+    /// Subscribes to changes of the clipboard and the primary selection,
+    /// whether made by us or by other applications.
+    ///
+    /// Events can be received until the returned [`mpsc::Receiver`] is
+    /// dropped.
+    pub fn subscribe(&self) -> Result<mpsc::Receiver<ClipboardEvent>, Error> {
+        self.raw.subscribe()
+    }
+
+    /// Lists the formats the clipboard contents are currently available in,
+    /// without transferring them.
+    pub fn available_formats(&self) -> Result<Vec<Format>, Error> {
+        self.raw.available_formats()
+    }
+
+    /// Writes every representation of the given [`ClipboardContent`] to the
+    /// clipboard at once.
+    pub fn write_content(
+        &mut self,
+        content: ClipboardContent,
+    ) -> Result<(), Error> {
+        self.raw.write_content(content)
+    }
+
+    /// Offers the clipboard contents as each of the given MIME types,
+    /// without producing them up front.
+    ///
+    /// `provider` is called from a background thread with the requested MIME
+    /// type every time another application pastes. Wrap it with [`cached`]
+    /// to produce each type at most once.
+    pub fn write_lazy<F>(
+        &mut self,
+        mimes: &[&str],
+        provider: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(
+                &str,
+            )
+                -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.raw.write_lazy(mimes, Box::new(provider))
+    }
+
+    /// Like [`Clipboard::write_content`], but returning a future that
+    /// completes once the contents are offered to other applications.
+    pub fn write_content_async(
+        &mut self,
+        content: ClipboardContent,
+    ) -> ClipboardFuture<()> {
+        self.raw.write_content_async(content)
+    }
+
+    /// Hands the clipboard contents we own over to the clipboard manager, so
+    /// they stay available once the application exits.
+    ///
+    /// Call this right before exiting. Only X11 needs it; it fails with
+    /// [`Error::Unsupported`] elsewhere, and with
+    /// [`Error::NoClipboardManager`] if no clipboard manager is running.
+    pub fn store_on_exit(&self) -> Result<(), Error> {
+        self.raw.store_on_exit()
+    }
 }
 
 impl Clipboard {
-    pub fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
+    pub fn read_primary(&self) -> Result<String, Error> {
         self.raw.read_primary()
     }
 
-    pub fn write_primary(&mut self, contents: String) -> Option<Result<(), Box<dyn Error>>> {
+    pub fn write_primary(&mut self, contents: String) -> Result<(), Error> {
         self.raw.write_primary(contents)
     }
+
+    /// Lists the formats the primary selection is currently available in,
+    /// without transferring it.
+    pub fn available_primary_formats(&self) -> Result<Vec<Format>, Error> {
+        self.raw.available_primary_formats()
+    }
 }
 
+/// The platform side of a [`Clipboard`].
+///
+/// Only [`read`](ClipboardProvider::read) and
+/// [`write`](ClipboardProvider::write) are required. Everything else fails
+/// with [`Error::Unsupported`] unless implemented.
 pub trait ClipboardProvider {
-    fn read(&self) -> Result<String, Box<dyn Error>>;
+    fn read(&self) -> Result<String, Error>;
+
+    fn write(&mut self, contents: String) -> Result<(), Error>;
+
+    /// Falls back to [`ClipboardProvider::read`] unless implemented.
+    fn read_at(&self, _time: u32) -> Result<String, Error> {
+        self.read()
+    }
+
+    /// Falls back to [`ClipboardProvider::write`] unless implemented.
+    fn write_at(&mut self, contents: String, _time: u32) -> Result<(), Error> {
+        self.write(contents)
+    }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>>;
+    fn read_primary(&self) -> Result<String, Error> {
+        Err(Error::Unsupported)
+    }
 
-    fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
-        None
+    fn write_primary(&mut self, _contents: String) -> Result<(), Error> {
+        Err(Error::Unsupported)
     }
 
-    fn write_primary(&mut self, _contents: String) -> Option<Result<(), Box<dyn Error>>> {
-        None
+    fn read_mime(&self, _mimes: &[&str]) -> Result<(String, Vec<u8>), Error> {
+        Err(Error::Unsupported)
     }
+
+    fn write_content(
+        &mut self,
+        _content: ClipboardContent,
+    ) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn write_lazy(
+        &mut self,
+        _mimes: &[&str],
+        _provider: DataProvider,
+    ) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn available_formats(&self) -> Result<Vec<Format>, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn read_async(&self) -> ClipboardFuture<String> {
+        unsupported()
+    }
+
+    fn write_async(&mut self, _contents: String) -> ClipboardFuture<()> {
+        unsupported()
+    }
+
+    fn read_mime_async(
+        &self,
+        _mimes: &[&str],
+    ) -> ClipboardFuture<(String, Vec<u8>)> {
+        unsupported()
+    }
+
+    fn write_content_async(
+        &mut self,
+        _content: ClipboardContent,
+    ) -> ClipboardFuture<()> {
+        unsupported()
+    }
+
+    fn available_primary_formats(&self) -> Result<Vec<Format>, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<ClipboardEvent>, Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn store_on_exit(&self) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+}
+
+fn unsupported<T: Send + 'static>() -> ClipboardFuture<T> {
+    Box::pin(future::ready(Err(Error::Unsupported)))
 }
@@ -0,0 +1,645 @@
+//! An in-memory clipboard, to test applications without a display server.
+//!
+//! A [`MemoryClipboard`] is a handle to a clipboard shared by all of its
+//! clones: one can be given to a [`Clipboard`](crate::Clipboard) while
+//! another plays the part of the other applications.
+//!
+//! ```
+//! use window_clipboard::memory::MemoryClipboard;
+//! use window_clipboard::{Clipboard, ClipboardContent, Selection};
+//!
+//! # fn main() -> Result<(), window_clipboard::Error> {
+//! let memory = MemoryClipboard::new();
+//! let mut clipboard = Clipboard::from_provider(Box::new(memory.clone()));
+//!
+//! clipboard.write(String::from("Hello"))?;
+//! assert!(memory.is_owned(Selection::Clipboard));
+//!
+//! // Another application copies something.
+//! let content = ClipboardContent::new().text("Bye");
+//! memory.set_contents(Selection::Clipboard, content);
+//! assert_eq!(clipboard.read()?, "Bye");
+//! assert!(!memory.is_owned(Selection::Clipboard));
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    ClipboardContent, ClipboardEvent, ClipboardFuture, ClipboardProvider,
+    DataProvider, Error, Format, Selection,
+};
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+/// The MIME types text is read as, in order of preference.
+const TEXT_MIME_TYPES: [&str; 2] = [ClipboardContent::TEXT, "text/plain"];
+
+/// A [`ClipboardProvider`] keeping the clipboard and the primary selection
+/// in memory.
+///
+/// Clones share the same selections.
+#[derive(Clone, Default)]
+pub struct MemoryClipboard {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    clipboard: Slot,
+    primary: Slot,
+    delay: Option<Duration>,
+    /// Injected errors, returned by the next operations in order.
+    errors: VecDeque<Error>,
+    subscribers: Vec<mpsc::Sender<ClipboardEvent>>,
+}
+
+/// The contents of a selection.
+#[derive(Default)]
+struct Slot {
+    formats: Vec<(String, Data)>,
+    /// Whether the contents were written through the provider, rather than
+    /// by another application.
+    is_owned: bool,
+}
+
+#[derive(Clone)]
+enum Data {
+    Ready(Vec<u8>),
+    Lazy(Arc<DataProvider>),
+}
+
+impl MemoryClipboard {
+    /// Creates an empty [`MemoryClipboard`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the contents of `selection` as another application would,
+    /// taking ownership of it.
+    pub fn set_contents(
+        &self,
+        selection: Selection,
+        content: ClipboardContent,
+    ) {
+        self.lock().replace(selection, ready(content), false);
+    }
+
+    /// Empties `selection`, as if the application owning it exited.
+    pub fn clear(&self, selection: Selection) {
+        self.lock().replace(selection, Vec::new(), false);
+    }
+
+    /// Returns the current contents of `selection`.
+    ///
+    /// Lazy contents are produced on the spot. Formats whose provider fails
+    /// are left out.
+    pub fn contents(&self, selection: Selection) -> ClipboardContent {
+        let formats = self.lock().slot(selection).formats.clone();
+
+        formats
+            .into_iter()
+            .filter_map(|(mime, data)| {
+                let data = data.load(&mime).ok()?;
+
+                Some((mime, data))
+            })
+            .fold(ClipboardContent::new(), |content, (mime, data)| {
+                content.with(mime, data)
+            })
+    }
+
+    /// Returns `true` if the contents of `selection` were written through
+    /// the provider, and no other application replaced them since.
+    pub fn is_owned(&self, selection: Selection) -> bool {
+        self.lock().slot(selection).is_owned
+    }
+
+    /// Makes every following operation take `delay` before completing, or
+    /// none if `None`.
+    ///
+    /// Blocking operations block for that long, while asynchronous ones only
+    /// complete once it has passed.
+    pub fn set_delay(&self, delay: Option<Duration>) {
+        self.lock().delay = delay;
+    }
+
+    /// Makes the next operation fail with `error`.
+    ///
+    /// Errors are queued, so several of them fail as many operations in
+    /// order.
+    pub fn fail_next(&self, error: Error) {
+        self.lock().errors.push_back(error);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits for the configured delay, and returns the next injected error.
+    fn begin(&self) -> Result<(), Error> {
+        let (delay, error) = {
+            let mut shared = self.lock();
+
+            (shared.delay, shared.errors.pop_front())
+        };
+
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Runs `operation` asynchronously, after the configured delay.
+    fn spawn<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&Self) -> Result<T, Error> + Send + 'static,
+    ) -> ClipboardFuture<T> {
+        let delay = self.lock().delay;
+        let clipboard = self.clone();
+
+        Box::pin(async move {
+            if let Some(delay) = delay {
+                Sleep::new(delay).await;
+            }
+
+            let error = clipboard.lock().errors.pop_front();
+
+            match error {
+                Some(error) => Err(error),
+                None => operation(&clipboard),
+            }
+        })
+    }
+
+    fn write_formats(
+        &self,
+        selection: Selection,
+        formats: Vec<(String, Data)>,
+    ) -> Result<(), Error> {
+        self.begin()?;
+        self.lock().replace(selection, formats, true);
+
+        Ok(())
+    }
+
+    fn load(
+        &self,
+        selection: Selection,
+        mimes: &[&str],
+    ) -> Result<(String, Vec<u8>), Error> {
+        let formats = self.lock().slot(selection).formats.clone();
+
+        if formats.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        let (mime, data) = mimes
+            .iter()
+            .find_map(|mime| formats.iter().find(|(format, _)| format == mime))
+            .ok_or(Error::FormatUnavailable)?;
+
+        Ok((mime.clone(), data.load(mime)?))
+    }
+
+    fn read_text(&self, selection: Selection) -> Result<String, Error> {
+        let (_, data) = self.load(selection, &TEXT_MIME_TYPES)?;
+
+        String::from_utf8(data).map_err(Error::invalid_data)
+    }
+
+    fn formats(&self, selection: Selection) -> Vec<Format> {
+        self.lock()
+            .slot(selection)
+            .formats
+            .iter()
+            .map(|(mime, data)| Format {
+                mime: mime.clone(),
+                size_hint: match data {
+                    Data::Ready(data) => Some(data.len()),
+                    Data::Lazy(_) => None,
+                },
+            })
+            .collect()
+    }
+}
+
+impl Shared {
+    fn slot(&self, selection: Selection) -> &Slot {
+        match selection {
+            Selection::Clipboard => &self.clipboard,
+            Selection::Primary => &self.primary,
+        }
+    }
+
+    fn replace(
+        &mut self,
+        selection: Selection,
+        formats: Vec<(String, Data)>,
+        is_owned: bool,
+    ) {
+        let slot = match selection {
+            Selection::Clipboard => &mut self.clipboard,
+            Selection::Primary => &mut self.primary,
+        };
+
+        let was_owned = slot.is_owned;
+        *slot = Slot { formats, is_owned };
+
+        if was_owned && !is_owned {
+            self.notify(ClipboardEvent::OwnershipLost(selection));
+        }
+
+        self.notify(ClipboardEvent::Changed(selection));
+    }
+
+    fn notify(&mut self, event: ClipboardEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
+
+impl Data {
+    fn load(&self, mime: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Data::Ready(data) => Ok(data.clone()),
+            Data::Lazy(provider) => (**provider)(mime).map_err(Error::Backend),
+        }
+    }
+}
+
+impl ClipboardProvider for MemoryClipboard {
+    fn read(&self) -> Result<String, Error> {
+        self.begin()?;
+        self.read_text(Selection::Clipboard)
+    }
+
+    fn write(&mut self, contents: String) -> Result<(), Error> {
+        self.write_content(ClipboardContent::new().text(contents))
+    }
+
+    fn read_primary(&self) -> Result<String, Error> {
+        self.begin()?;
+        self.read_text(Selection::Primary)
+    }
+
+    fn write_primary(&mut self, contents: String) -> Result<(), Error> {
+        self.write_formats(
+            Selection::Primary,
+            ready(ClipboardContent::new().text(contents)),
+        )
+    }
+
+    fn read_mime(&self, mimes: &[&str]) -> Result<(String, Vec<u8>), Error> {
+        self.begin()?;
+        self.load(Selection::Clipboard, mimes)
+    }
+
+    fn write_content(
+        &mut self,
+        content: ClipboardContent,
+    ) -> Result<(), Error> {
+        self.write_formats(Selection::Clipboard, ready(content))
+    }
+
+    fn write_lazy(
+        &mut self,
+        mimes: &[&str],
+        provider: DataProvider,
+    ) -> Result<(), Error> {
+        let provider = Arc::new(provider);
+
+        let formats = mimes
+            .iter()
+            .map(|&mime| (mime.to_owned(), Data::Lazy(Arc::clone(&provider))))
+            .collect();
+
+        self.write_formats(Selection::Clipboard, formats)
+    }
+
+    fn available_formats(&self) -> Result<Vec<Format>, Error> {
+        self.begin()?;
+
+        Ok(self.formats(Selection::Clipboard))
+    }
+
+    fn read_async(&self) -> ClipboardFuture<String> {
+        self.spawn(|clipboard| clipboard.read_text(Selection::Clipboard))
+    }
+
+    fn write_async(&mut self, contents: String) -> ClipboardFuture<()> {
+        self.write_content_async(ClipboardContent::new().text(contents))
+    }
+
+    fn read_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> ClipboardFuture<(String, Vec<u8>)> {
+        let mimes: Vec<String> =
+            mimes.iter().map(|&mime| mime.to_owned()).collect();
+
+        self.spawn(move |clipboard| {
+            let mimes: Vec<&str> = mimes.iter().map(String::as_str).collect();
+
+            clipboard.load(Selection::Clipboard, &mimes)
+        })
+    }
+
+    fn write_content_async(
+        &mut self,
+        content: ClipboardContent,
+    ) -> ClipboardFuture<()> {
+        self.spawn(move |clipboard| {
+            clipboard.lock().replace(
+                Selection::Clipboard,
+                ready(content),
+                true,
+            );
+
+            Ok(())
+        })
+    }
+
+    fn available_primary_formats(&self) -> Result<Vec<Format>, Error> {
+        self.begin()?;
+
+        Ok(self.formats(Selection::Primary))
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<ClipboardEvent>, Error> {
+        self.begin()?;
+
+        let (sender, receiver) = mpsc::channel();
+        self.lock().subscribers.push(sender);
+
+        Ok(receiver)
+    }
+}
+
+fn ready(content: ClipboardContent) -> Vec<(String, Data)> {
+    content
+        .into_formats()
+        .into_iter()
+        .map(|(mime, data)| (mime, Data::Ready(data)))
+        .collect()
+}
+
+/// A future completing once a duration has passed, measured on a thread of
+/// its own.
+struct Sleep {
+    state: Arc<Mutex<(bool, Option<Waker>)>>,
+}
+
+impl Sleep {
+    fn new(duration: Duration) -> Self {
+        let state = Arc::new(Mutex::new((false, None::<Waker>)));
+        let timer = Arc::clone(&state);
+
+        let _ = thread::spawn(move || {
+            thread::sleep(duration);
+
+            let mut state =
+                timer.lock().unwrap_or_else(PoisonError::into_inner);
+            state.0 = true;
+
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        });
+
+        Sleep { state }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        let mut state =
+            self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if state.0 {
+            Poll::Ready(())
+        } else {
+            state.1 = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use std::time::Instant;
+
+    /// Polls `future` on the current thread until it completes.
+    fn block_on<T>(mut future: ClipboardFuture<T>) -> Result<T, Error> {
+        struct Unparker(thread::Thread);
+
+        impl Wake for Unparker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unparker(thread::current())));
+        let mut context = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn writes_take_ownership_until_another_application_copies() {
+        let mut memory = MemoryClipboard::new();
+        let events = memory.subscribe().unwrap();
+
+        memory.write(String::from("Hello")).unwrap();
+        assert!(memory.is_owned(Selection::Clipboard));
+        assert!(!memory.is_owned(Selection::Primary));
+
+        memory.set_contents(
+            Selection::Clipboard,
+            ClipboardContent::new().text("Bye"),
+        );
+        assert!(!memory.is_owned(Selection::Clipboard));
+        assert_eq!(memory.read().unwrap(), "Bye");
+
+        memory.clear(Selection::Clipboard);
+        assert!(matches!(memory.read(), Err(Error::Empty)));
+
+        let events: Vec<ClipboardEvent> = events.try_iter().collect();
+
+        assert_eq!(
+            events,
+            [
+                ClipboardEvent::Changed(Selection::Clipboard),
+                ClipboardEvent::OwnershipLost(Selection::Clipboard),
+                ClipboardEvent::Changed(Selection::Clipboard),
+                ClipboardEvent::Changed(Selection::Clipboard),
+            ]
+        );
+    }
+
+    #[test]
+    fn selections_are_separate() {
+        let mut memory = MemoryClipboard::new();
+
+        memory.write_primary(String::from("Primary")).unwrap();
+        assert!(memory.is_owned(Selection::Primary));
+        assert!(matches!(memory.read(), Err(Error::Empty)));
+        assert_eq!(memory.read_primary().unwrap(), "Primary");
+    }
+
+    #[test]
+    fn failures_are_consumed_in_order() {
+        let mut memory = MemoryClipboard::new();
+        memory.write(String::from("Hello")).unwrap();
+
+        memory.fail_next(Error::Timeout);
+        memory.fail_next(Error::Unsupported);
+
+        assert!(matches!(memory.read(), Err(Error::Timeout)));
+        assert!(matches!(
+            memory.write(String::from("Bye")),
+            Err(Error::Unsupported)
+        ));
+
+        // The failed write left the clipboard alone.
+        assert_eq!(memory.read().unwrap(), "Hello");
+
+        memory.fail_next(Error::Timeout);
+        assert!(matches!(block_on(memory.read_async()), Err(Error::Timeout)));
+        assert_eq!(block_on(memory.read_async()).unwrap(), "Hello");
+    }
+
+    #[test]
+    fn read_mime_follows_preferences() {
+        let memory = MemoryClipboard::new();
+
+        memory.set_contents(
+            Selection::Clipboard,
+            ClipboardContent::new()
+                .text("Hello")
+                .with("text/html", "<b>Hello</b>"),
+        );
+
+        let (mime, data) =
+            memory.read_mime(&["image/png", "text/html"]).unwrap();
+        assert_eq!(mime, "text/html");
+        assert_eq!(data, b"<b>Hello</b>");
+
+        assert!(matches!(
+            memory.read_mime(&["image/png"]),
+            Err(Error::FormatUnavailable)
+        ));
+
+        let formats = memory.available_formats().unwrap();
+        assert!(formats.contains(&Format {
+            mime: String::from("text/html"),
+            size_hint: Some(12),
+        }));
+    }
+
+    #[test]
+    fn lazy_providers_are_called_on_every_read() {
+        let mut memory = MemoryClipboard::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+
+        memory
+            .write_lazy(
+                &["text/html", "image/png"],
+                Box::new(move |mime| {
+                    let _ = counter.fetch_add(1, Ordering::Relaxed);
+
+                    match mime {
+                        "text/html" => Ok(b"<b>Hello</b>".to_vec()),
+                        _ => Err("not now".into()),
+                    }
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        assert!(memory
+            .available_formats()
+            .unwrap()
+            .iter()
+            .all(|format| format.size_hint.is_none()));
+
+        assert_eq!(
+            memory.read_mime(&["text/html"]).unwrap().1,
+            b"<b>Hello</b>"
+        );
+        assert_eq!(
+            memory.read_mime(&["text/html"]).unwrap().1,
+            b"<b>Hello</b>"
+        );
+        assert!(matches!(
+            memory.read_mime(&["image/png"]),
+            Err(Error::Backend(_))
+        ));
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        // Failing formats are left out of the contents.
+        let contents = memory.contents(Selection::Clipboard);
+        assert_eq!(contents.get("text/html"), Some(&b"<b>Hello</b>"[..]));
+        assert_eq!(contents.get("image/png"), None);
+    }
+
+    #[test]
+    fn async_operations_complete_after_the_delay() {
+        let mut memory = MemoryClipboard::new();
+        memory.set_delay(Some(Duration::from_millis(50)));
+
+        let start = Instant::now();
+        let write = memory.write_async(String::from("Hello"));
+
+        // Nothing happens until the delay has passed.
+        assert!(!memory.is_owned(Selection::Clipboard));
+
+        block_on(write).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(memory.is_owned(Selection::Clipboard));
+
+        let start = Instant::now();
+        assert_eq!(block_on(memory.read_async()).unwrap(), "Hello");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let start = Instant::now();
+        let (mime, _) =
+            block_on(memory.read_mime_async(&[ClipboardContent::TEXT]))
+                .unwrap();
+        assert_eq!(mime, ClipboardContent::TEXT);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        memory.set_delay(None);
+
+        let start = Instant::now();
+        assert_eq!(memory.read().unwrap(), "Hello");
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn blocking_operations_wait_for_the_delay() {
+        let mut memory = MemoryClipboard::new();
+        memory.set_delay(Some(Duration::from_millis(50)));
+
+        let start = Instant::now();
+        memory.write(String::from("Hello")).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::ClipboardProvider;
+use crate::{ClipboardBuilder, ClipboardProvider, Error};
 
 use raw_window_handle::HasDisplayHandle;
 
@@ -6,25 +6,23 @@ struct Dummy;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn std::error::Error>> {
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
     Ok(Box::new(Dummy))
 }
 
+pub fn connect_headless(
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    Err(Error::Unsupported)
+}
+
 impl ClipboardProvider for Dummy {
-    fn read(&self) -> Result<String, Box<dyn std::error::Error>> {
-        Err(Box::new(Error::Unimplemented))
+    fn read(&self) -> Result<String, Error> {
+        Err(Error::Unsupported)
     }
 
-    fn write(
-        &mut self,
-        _contents: String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        Err(Box::new(Error::Unimplemented))
+    fn write(&mut self, _contents: String) -> Result<(), Error> {
+        Err(Error::Unsupported)
     }
 }
-
-#[derive(Debug, Clone, Copy, thiserror::Error)]
-enum Error {
-    #[error("unimplemented")]
-    Unimplemented,
-}
@@ -1,42 +1,34 @@
-use crate::ClipboardProvider;
+use crate::{ClipboardBuilder, ClipboardProvider, Error};
 
 use raw_window_handle::HasDisplayHandle;
-use std::error::Error;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    Ok(Box::new(Clipboard::new()?))
+}
+
+pub fn connect_headless(
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
     Ok(Box::new(Clipboard::new()?))
 }
 
 pub struct Clipboard;
 
 impl Clipboard {
-    pub fn new() -> Result<Clipboard, Box<dyn Error>> {
+    pub fn new() -> Result<Clipboard, Error> {
         Ok(Self)
     }
 }
 
-#[derive(Debug)]
-#[allow(non_camel_case_types)]
-pub enum iOSClipboardError {
-    Unimplemented,
-}
-
-impl std::fmt::Display for iOSClipboardError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Unimplemented")
-    }
-}
-
-impl Error for iOSClipboardError {}
-
 impl ClipboardProvider for Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        Err(Box::new(iOSClipboardError::Unimplemented))
+    fn read(&self) -> Result<String, Error> {
+        Err(Error::Unsupported)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        Err(Box::new(iOSClipboardError::Unimplemented))
+    fn write(&mut self, _contents: String) -> Result<(), Error> {
+        Err(Error::Unsupported)
     }
 }
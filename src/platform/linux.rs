@@ -1,56 +1,395 @@
-use crate::ClipboardProvider;
+use crate::{
+    ClipboardBuilder, ClipboardContent, ClipboardEvent, ClipboardFuture,
+    ClipboardProvider, DataProvider, Error, Format, Selection,
+};
 
 use raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
-use std::error::Error;
+use std::future::Future;
+use std::sync::mpsc;
 
 pub use clipboard_wayland as wayland;
 pub use clipboard_x11 as x11;
 
 pub unsafe fn connect<W: HasDisplayHandle>(
     window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
-    let clipboard = match window.display_handle()?.as_raw() {
+    builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    let display = window.display_handle().map_err(Error::backend)?.as_raw();
+
+    // Connect to the same X11 display as the window, rather than to the one
+    // named by `$DISPLAY`.
+    let x11_display = match display {
+        RawDisplayHandle::Xlib(handle) => handle
+            .display
+            .and_then(|display| x11::xlib_display_name(display, handle.screen)),
+        RawDisplayHandle::Xcb(handle) => {
+            handle.connection.and_then(|connection| {
+                x11::xcb_display_name(connection, handle.screen)
+            })
+        }
+        _ => None,
+    };
+
+    let clipboard = match display {
         RawDisplayHandle::Wayland(handle) => {
-            Box::new(wayland::Clipboard::connect(handle.display.as_ptr())) as _
+            Box::new(wayland::Clipboard::connect_with(
+                handle.display.as_ptr(),
+                wayland_settings(builder),
+            )) as _
+        }
+        _ => {
+            let settings = x11_settings(builder);
+
+            let clipboard = match x11_display {
+                Some(display) => {
+                    x11::Clipboard::connect_to_with(&display, settings)?
+                }
+                None => x11::Clipboard::connect_with(settings)?,
+            };
+
+            Box::new(clipboard) as _
         }
-        _ => Box::new(x11::Clipboard::connect()?) as _,
     };
 
     Ok(clipboard)
 }
 
+pub fn connect_headless(
+    builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    let wayland =
+        wayland::Clipboard::connect_to_env_with(wayland_settings(builder)).ok();
+
+    // Without a window, Wayland selections are only available through data
+    // control. Otherwise, X11 is likely to work better, through XWayland.
+    let wayland = match wayland {
+        Some(clipboard) if clipboard.has_data_control() == Some(true) => {
+            return Ok(Box::new(clipboard));
+        }
+        wayland => wayland,
+    };
+
+    if let Ok(clipboard) = x11::Clipboard::connect_with(x11_settings(builder)) {
+        return Ok(Box::new(clipboard));
+    }
+
+    match wayland {
+        Some(clipboard) => Ok(Box::new(clipboard)),
+        None => Err(Error::Unsupported),
+    }
+}
+
+fn wayland_settings(builder: &ClipboardBuilder) -> wayland::Settings {
+    wayland::Settings {
+        read_timeout: builder.read_timeout,
+        max_transfer_size: builder.max_transfer_size,
+    }
+}
+
+fn x11_settings(builder: &ClipboardBuilder) -> x11::Settings {
+    x11::Settings {
+        read_timeout: builder.read_timeout,
+        max_transfer_size: builder.max_transfer_size,
+        incr_chunk_size: builder.incr_chunk_size,
+    }
+}
+
 impl ClipboardProvider for wayland::Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.read()
+    fn read(&self) -> Result<String, Error> {
+        Ok(self.read()?)
+    }
+
+    fn read_primary(&self) -> Result<String, Error> {
+        Ok(self.read_primary()?)
+    }
+
+    fn write(&mut self, contents: String) -> Result<(), Error> {
+        Ok(self.write(contents)?)
     }
 
-    fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
-        Some(self.read_primary())
+    fn write_at(&mut self, contents: String, serial: u32) -> Result<(), Error> {
+        Ok(self.write_at(contents, serial)?)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.write(contents)
+    fn write_primary(&mut self, contents: String) -> Result<(), Error> {
+        Ok(self.write_primary(contents)?)
     }
 
-    fn write_primary(&mut self, contents: String) -> Option<Result<(), Box<dyn Error>>> {
-        Some(self.write_primary(contents))
+    fn read_mime(&self, mimes: &[&str]) -> Result<(String, Vec<u8>), Error> {
+        Ok(self.read_mime(mimes)?)
+    }
+
+    fn write_content(
+        &mut self,
+        content: ClipboardContent,
+    ) -> Result<(), Error> {
+        Ok(self.write_mime(content.into_formats())?)
+    }
+
+    fn write_lazy(
+        &mut self,
+        mimes: &[&str],
+        provider: DataProvider,
+    ) -> Result<(), Error> {
+        Ok(self.write_lazy(mimes, provider)?)
+    }
+
+    fn available_formats(&self) -> Result<Vec<Format>, Error> {
+        Ok(from_mimes(self.available_formats()?))
+    }
+
+    fn read_async(&self) -> ClipboardFuture<String> {
+        boxed(self.read_async())
+    }
+
+    fn write_async(&mut self, contents: String) -> ClipboardFuture<()> {
+        boxed(self.write_async(contents))
+    }
+
+    fn read_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> ClipboardFuture<(String, Vec<u8>)> {
+        boxed(self.read_mime_async(mimes))
+    }
+
+    fn write_content_async(
+        &mut self,
+        content: ClipboardContent,
+    ) -> ClipboardFuture<()> {
+        boxed(self.write_mime_async(content.into_formats()))
+    }
+
+    fn available_primary_formats(&self) -> Result<Vec<Format>, Error> {
+        Ok(from_mimes(self.available_primary_formats()?))
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<ClipboardEvent>, Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.subscribe(move |event| {
+            let event = match event {
+                wayland::ClipboardEvent::Changed(selection) => {
+                    ClipboardEvent::Changed(from_wayland(selection))
+                }
+                wayland::ClipboardEvent::OwnershipLost(selection) => {
+                    ClipboardEvent::OwnershipLost(from_wayland(selection))
+                }
+            };
+
+            sender.send(event).is_ok()
+        })?;
+
+        Ok(receiver)
     }
 }
 
 impl ClipboardProvider for x11::Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.read().map_err(Box::from)
+    fn read(&self) -> Result<String, Error> {
+        Ok(self.read()?)
+    }
+
+    fn read_primary(&self) -> Result<String, Error> {
+        Ok(self.read_primary()?)
+    }
+
+    fn write(&mut self, contents: String) -> Result<(), Error> {
+        Ok(self.write(contents)?)
+    }
+
+    fn read_at(&self, time: u32) -> Result<String, Error> {
+        Ok(self.read_at(time)?)
+    }
+
+    fn write_at(&mut self, contents: String, time: u32) -> Result<(), Error> {
+        Ok(self.write_at(contents, time)?)
+    }
+
+    fn write_primary(&mut self, contents: String) -> Result<(), Error> {
+        Ok(self.write_primary(contents)?)
+    }
+
+    fn read_mime(&self, mimes: &[&str]) -> Result<(String, Vec<u8>), Error> {
+        Ok(self.read_mime(mimes)?)
+    }
+
+    fn write_content(
+        &mut self,
+        content: ClipboardContent,
+    ) -> Result<(), Error> {
+        Ok(self.write_mime(content.into_formats())?)
+    }
+
+    fn write_lazy(
+        &mut self,
+        mimes: &[&str],
+        provider: DataProvider,
+    ) -> Result<(), Error> {
+        Ok(self.write_lazy(mimes, provider)?)
+    }
+
+    fn available_formats(&self) -> Result<Vec<Format>, Error> {
+        Ok(from_targets(self.available_formats()?))
+    }
+
+    fn read_async(&self) -> ClipboardFuture<String> {
+        boxed(self.read_async())
+    }
+
+    fn write_async(&mut self, contents: String) -> ClipboardFuture<()> {
+        boxed(self.write_async(contents))
+    }
+
+    fn read_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> ClipboardFuture<(String, Vec<u8>)> {
+        boxed(self.read_mime_async(mimes))
+    }
+
+    fn write_content_async(
+        &mut self,
+        content: ClipboardContent,
+    ) -> ClipboardFuture<()> {
+        boxed(self.write_mime_async(content.into_formats()))
+    }
+
+    fn available_primary_formats(&self) -> Result<Vec<Format>, Error> {
+        Ok(from_targets(self.available_primary_formats()?))
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<ClipboardEvent>, Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.subscribe(move |event| {
+            let event = match event {
+                x11::ClipboardEvent::Changed(selection) => {
+                    from_x11(selection).map(ClipboardEvent::Changed)
+                }
+                x11::ClipboardEvent::OwnershipLost(selection) => {
+                    from_x11(selection).map(ClipboardEvent::OwnershipLost)
+                }
+            };
+
+            match event {
+                Some(event) => sender.send(event).is_ok(),
+                // We never own the other selections.
+                None => true,
+            }
+        })?;
+
+        Ok(receiver)
+    }
+
+    fn store_on_exit(&self) -> Result<(), Error> {
+        Ok(self.store_on_exit()?)
+    }
+}
+
+impl From<wayland::Error> for Error {
+    fn from(error: wayland::Error) -> Self {
+        match error {
+            wayland::Error::Empty => Error::Empty,
+            wayland::Error::NoMimeType(_) => Error::FormatUnavailable,
+            wayland::Error::Timeout => Error::Timeout,
+            wayland::Error::TooLarge(max) => Error::TooLarge(max),
+            wayland::Error::Unsupported(_) => Error::Unsupported,
+            wayland::Error::InvalidUtf8(_) => Error::invalid_data(error),
+            _ => Error::backend(error),
+        }
+    }
+}
+
+impl From<x11::Error> for Error {
+    fn from(error: x11::Error) -> Self {
+        match error {
+            x11::Error::Empty => Error::Empty,
+            x11::Error::Timeout => Error::Timeout,
+            x11::Error::NoConversion => Error::FormatUnavailable,
+            x11::Error::TooLarge(max) => Error::TooLarge(max),
+            x11::Error::MissingExtension(_) => Error::Unsupported,
+            x11::Error::NoClipboardManager => Error::NoClipboardManager,
+            x11::Error::InvalidUtf8(_)
+            | x11::Error::InvalidText(_)
+            | x11::Error::UnexpectedType(_) => Error::invalid_data(error),
+            _ => Error::backend(error),
+        }
+    }
+}
+
+fn boxed<T, E>(
+    future: impl Future<Output = Result<T, E>> + Send + 'static,
+) -> ClipboardFuture<T>
+where
+    Error: From<E>,
+{
+    Box::pin(async move { Ok(future.await?) })
+}
+
+fn from_wayland(selection: wayland::Selection) -> Selection {
+    match selection {
+        wayland::Selection::Clipboard => Selection::Clipboard,
+        wayland::Selection::Primary => Selection::Primary,
     }
+}
 
-    fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
-        Some(self.read_primary().map_err(Box::from))
+fn from_x11(selection: x11::Selection) -> Option<Selection> {
+    match selection {
+        x11::Selection::Clipboard => Some(Selection::Clipboard),
+        x11::Selection::Primary => Some(Selection::Primary),
+        x11::Selection::Secondary | x11::Selection::Named(_) => None,
     }
+}
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.write(contents).map_err(Box::from)
+fn from_mimes(mimes: Vec<String>) -> Vec<Format> {
+    mimes
+        .into_iter()
+        .map(|mime| Format {
+            mime,
+            size_hint: None,
+        })
+        .collect()
+}
+
+fn from_targets(targets: Vec<(String, Option<usize>)>) -> Vec<Format> {
+    targets
+        .into_iter()
+        .map(|(mime, size_hint)| Format { mime, size_hint })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_x11_values_are_invalid_data() {
+        let errors = [
+            x11::Error::InvalidText("truncated escape sequence"),
+            x11::Error::UnexpectedType(1),
+            x11::Error::InvalidUtf8(String::from_utf8(vec![0xFF]).unwrap_err()),
+        ];
+
+        for error in errors {
+            assert!(matches!(Error::from(error), Error::InvalidData(_)));
+        }
     }
 
-    fn write_primary(&mut self, contents: String) -> Option<Result<(), Box<dyn Error>>> {
-        Some(self.write_primary(contents).map_err(Box::from))
+    #[test]
+    fn x11_errors_are_mapped() {
+        assert!(matches!(Error::from(x11::Error::Empty), Error::Empty));
+        assert!(matches!(Error::from(x11::Error::Timeout), Error::Timeout));
+        assert!(matches!(
+            Error::from(x11::Error::NoConversion),
+            Error::FormatUnavailable
+        ));
+        assert!(matches!(
+            Error::from(x11::Error::NoClipboardManager),
+            Error::NoClipboardManager
+        ));
+        assert!(matches!(
+            Error::from(x11::Error::InvalidOwner),
+            Error::Backend(_)
+        ));
     }
 }
@@ -1,20 +1,36 @@
-use crate::ClipboardProvider;
+use crate::{ClipboardBuilder, ClipboardProvider, Error};
 
 use raw_window_handle::HasDisplayHandle;
-use std::error::Error;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
-    Ok(Box::new(clipboard_macos::Clipboard::new()?))
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    Ok(Box::new(
+        clipboard_macos::Clipboard::new().map_err(backend)?,
+    ))
+}
+
+pub fn connect_headless(
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    Ok(Box::new(
+        clipboard_macos::Clipboard::new().map_err(backend)?,
+    ))
 }
 
 impl ClipboardProvider for clipboard_macos::Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.read()
+    fn read(&self) -> Result<String, Error> {
+        self.read().map_err(backend)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.write(contents)
+    fn write(&mut self, contents: String) -> Result<(), Error> {
+        self.write(contents).map_err(backend)
     }
 }
+
+/// The errors of `clipboard_macos` are not `Send`, so only their message is
+/// kept.
+fn backend(error: Box<dyn std::error::Error>) -> Error {
+    Error::backend(error.to_string())
+}
@@ -1,24 +1,29 @@
-use crate::ClipboardProvider;
+use crate::{ClipboardBuilder, ClipboardProvider, Error};
 
 use clipboard_win::{get_clipboard_string, set_clipboard_string};
 use raw_window_handle::HasDisplayHandle;
 
-use std::error::Error;
-
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    Ok(Box::new(Clipboard))
+}
+
+pub fn connect_headless(
+    _builder: &ClipboardBuilder,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
     Ok(Box::new(Clipboard))
 }
 
 pub struct Clipboard;
 
 impl ClipboardProvider for Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        Ok(get_clipboard_string()?)
+    fn read(&self) -> Result<String, Error> {
+        get_clipboard_string().map_err(Error::backend)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        Ok(set_clipboard_string(&contents)?)
+    fn write(&mut self, contents: String) -> Result<(), Error> {
+        set_clipboard_string(&contents).map_err(Error::backend)
     }
 }
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Mutex, PoisonError};
+
+/// Produces the clipboard contents as the given MIME type, when another
+/// application asks for it.
+pub type DataProvider = Box<
+    dyn Fn(&str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> + Send + Sync,
+>;
+
+/// Wraps a provider so every MIME type is only produced once, the first time
+/// it is pasted.
+///
+/// Failures are not cached, so the provider is called again on the next
+/// paste.
+pub fn cached<F>(
+    provider: F,
+) -> impl Fn(&str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> + Send + Sync
+where
+    F: Fn(&str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> + Send + Sync,
+{
+    let cache = Mutex::new(HashMap::<String, Vec<u8>>::new());
+
+    move |mime| {
+        let cached = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(mime)
+            .cloned();
+
+        if let Some(data) = cached {
+            return Ok(data);
+        }
+
+        let data = provider(mime)?;
+
+        let _ = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(mime.to_owned(), data.clone());
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{spawn_error, stop, Error, STARTUP_TIMEOUT};
+
+use std::ffi::OsStr;
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A headless compositor, its program, arguments and environment.
+struct Candidate {
+    program: &'static str,
+    args: &'static [&'static str],
+    env: &'static [(&'static str, &'static str)],
+}
+
+/// The compositors tried, in order.
+const CANDIDATES: &[Candidate] = &[
+    Candidate {
+        program: "weston",
+        args: &["--backend=headless", "--no-config"],
+        env: &[],
+    },
+    // Older versions of Weston only know backends by their module.
+    Candidate {
+        program: "weston",
+        args: &["--backend=headless-backend.so", "--no-config"],
+        env: &[],
+    },
+    Candidate {
+        program: "sway",
+        args: &["--config", "/dev/null"],
+        env: &[
+            ("WLR_BACKENDS", "headless"),
+            ("WLR_RENDERER", "pixman"),
+            ("WLR_LIBINPUT_NO_DEVICES", "1"),
+        ],
+    },
+];
+
+/// Tells the runtime directories of the compositors we start apart.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A private headless Wayland compositor, killed when dropped.
+///
+/// It runs in a runtime directory of its own, so it never takes the socket
+/// of the session compositor.
+#[derive(Debug)]
+pub struct WaylandCompositor {
+    process: Child,
+    runtime_dir: PathBuf,
+    socket: String,
+}
+
+impl WaylandCompositor {
+    /// Starts the first headless compositor that is installed, among Weston
+    /// and Sway, and waits until it accepts connections.
+    ///
+    /// Returns `None` if none of them is installed, so tests can be skipped.
+    pub fn start() -> Result<Option<Self>, Error> {
+        let mut result = Ok(None);
+
+        for candidate in CANDIDATES {
+            match Self::start_candidate(candidate) {
+                Ok(compositor) => return Ok(Some(compositor)),
+                Err(Error::NotInstalled(_)) => {}
+                Err(error) => result = Err(error),
+            }
+        }
+
+        result
+    }
+
+    fn start_candidate(candidate: &Candidate) -> Result<Self, Error> {
+        let runtime_dir = std::env::temp_dir().join(format!(
+            "clipboard-testing-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // Compositors refuse runtime directories others can access.
+        DirBuilder::new().mode(0o700).create(&runtime_dir)?;
+
+        let process = Command::new(candidate.program)
+            .args(candidate.args)
+            .envs(candidate.env.iter().copied())
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("WAYLAND_SOCKET")
+            .env_remove("DISPLAY")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut process = match process {
+            Ok(process) => process,
+            Err(error) => {
+                let _ = fs::remove_dir_all(&runtime_dir);
+
+                return Err(spawn_error(candidate.program, error));
+            }
+        };
+
+        match wait_for_socket(&mut process, &runtime_dir) {
+            Some(socket) => Ok(WaylandCompositor {
+                process,
+                runtime_dir,
+                socket,
+            }),
+            None => {
+                stop(&mut process);
+                let _ = fs::remove_dir_all(&runtime_dir);
+
+                Err(Error::StartupFailed(candidate.program))
+            }
+        }
+    }
+
+    /// The name of the socket of the compositor, to set as
+    /// `$WAYLAND_DISPLAY` along with [`WaylandCompositor::runtime_dir`].
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+
+    /// The runtime directory of the compositor, to set as
+    /// `$XDG_RUNTIME_DIR`.
+    pub fn runtime_dir(&self) -> &Path {
+        &self.runtime_dir
+    }
+
+    /// The full path of the socket of the compositor, which is also a valid
+    /// `$WAYLAND_DISPLAY` on its own.
+    pub fn socket_path(&self) -> PathBuf {
+        self.runtime_dir.join(&self.socket)
+    }
+
+    /// The environment variables clients need to connect to the compositor,
+    /// as taken by [`Command::envs`].
+    pub fn env(&self) -> [(&'static str, &OsStr); 2] {
+        [
+            ("XDG_RUNTIME_DIR", self.runtime_dir.as_os_str()),
+            ("WAYLAND_DISPLAY", OsStr::new(&self.socket)),
+        ]
+    }
+}
+
+impl Drop for WaylandCompositor {
+    fn drop(&mut self) {
+        stop(&mut self.process);
+        let _ = fs::remove_dir_all(&self.runtime_dir);
+    }
+}
+
+/// Wait for `process` to create a socket in `runtime_dir`, returning its
+/// name.
+///
+/// Returns `None` if it exits or takes too long.
+fn wait_for_socket(process: &mut Child, runtime_dir: &Path) -> Option<String> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+
+    while Instant::now() < deadline {
+        if !matches!(process.try_wait(), Ok(None)) {
+            return None;
+        }
+
+        let socket = fs::read_dir(runtime_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .find(|name| {
+                name.starts_with("wayland-") && !name.ends_with(".lock")
+            });
+
+        if socket.is_some() {
+            return socket;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    None
+}
@@ -0,0 +1,28 @@
+use x11rb::errors::{
+    ConnectError, ConnectionError, ReplyError, ReplyOrIdError,
+};
+
+#[must_use]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0} is not installed")]
+    NotInstalled(&'static str),
+    #[error("{0} failed to start")]
+    StartupFailed(&'static str),
+    #[error("connection failed: {0}")]
+    ConnectionFailed(#[from] ConnectError),
+    #[error("connection errored: {0}")]
+    ConnectionErrored(#[from] ConnectionError),
+    #[error("reply failed: {0}")]
+    ReplyError(#[from] ReplyError),
+    #[error("request failed: {0}")]
+    ReplyOrIdError(#[from] ReplyOrIdError),
+    #[error("timeout")]
+    Timeout,
+    #[error("the selection could not be owned")]
+    OwnershipRefused,
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("a background thread panicked")]
+    ThreadPanicked,
+}
@@ -0,0 +1,81 @@
+//! Display servers and foreign clipboard clients to test clipboard code
+//! against.
+//!
+//! An [`XServer`] runs a private Xvfb on a free display, so tests neither
+//! need nor disturb the session they run in. A [`WaylandCompositor`] does
+//! the same with a headless compositor, when one is installed.
+//!
+//! A [`SelectionOwner`] and a [`Requestor`] play the part of other X11
+//! applications, owning selections or asking for their values, including
+//! the `TARGETS`, `MULTIPLE` and `INCR` corners of the protocol.
+//!
+//! ```no_run
+//! use clipboard_testing::{Requestor, SelectionOwner, XServer};
+//!
+//! # fn main() -> Result<(), clipboard_testing::Error> {
+//! let server = XServer::start()?;
+//!
+//! let owner = SelectionOwner::new("CLIPBOARD")
+//!     .target("UTF8_STRING", "Hello")
+//!     .incr(2)
+//!     .spawn(server.display())?;
+//!
+//! let requestor = Requestor::connect(server.display())?;
+//! let value = requestor.convert("CLIPBOARD", "UTF8_STRING")?.unwrap();
+//!
+//! assert_eq!(value.data, b"Hello");
+//! assert!(value.is_incr);
+//! assert_eq!(owner.requests(), ["UTF8_STRING"]);
+//! # Ok(())
+//! # }
+//! ```
+#[cfg(unix)]
+mod compositor;
+mod error;
+mod owner;
+mod requestor;
+mod server;
+
+#[cfg(unix)]
+pub use compositor::WaylandCompositor;
+pub use error::Error;
+pub use owner::{OwnerHandle, SelectionOwner};
+pub use requestor::{Requestor, Value};
+pub use server::XServer;
+
+use x11rb::protocol::xproto::{self, Atom};
+use x11rb::rust_connection::RustConnection;
+
+use std::io;
+use std::process::Child;
+use std::time::Duration;
+
+/// How long display servers are given to start.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Turn a failure to spawn `program` into an [`Error`].
+fn spawn_error(program: &'static str, error: io::Error) -> Error {
+    if error.kind() == io::ErrorKind::NotFound {
+        Error::NotInstalled(program)
+    } else {
+        Error::Io(error)
+    }
+}
+
+/// Kill `process` and wait for it to exit.
+fn stop(process: &mut Child) {
+    let _ = process.kill();
+    let _ = process.wait();
+}
+
+fn intern(connection: &RustConnection, name: &str) -> Result<Atom, Error> {
+    Ok(xproto::intern_atom(connection, false, name.as_bytes())?
+        .reply()?
+        .atom)
+}
+
+fn atom_name(connection: &RustConnection, atom: Atom) -> Result<String, Error> {
+    let reply = xproto::get_atom_name(connection, atom)?.reply()?;
+
+    Ok(String::from_utf8_lossy(&reply.name).into_owned())
+}
@@ -0,0 +1,427 @@
+use crate::{atom_name, intern, Error};
+
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{
+    self, Atom, AtomEnum, ChangeWindowAttributesAux, CreateWindowAux,
+    EventMask, PropMode, Property, SelectionRequestEvent, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// Owns a selection on behalf of a foreign application, answering
+/// conversions from a thread of its own.
+///
+/// `TARGETS` is always answered with the configured targets.
+#[derive(Debug, Clone)]
+pub struct SelectionOwner {
+    selection: String,
+    targets: Vec<(String, Vec<u8>)>,
+    chunk_size: Option<usize>,
+    is_responsive: bool,
+}
+
+impl SelectionOwner {
+    /// Creates an owner of the selection with the given name, like
+    /// `CLIPBOARD` or `PRIMARY`, without any target.
+    pub fn new(selection: impl Into<String>) -> Self {
+        SelectionOwner {
+            selection: selection.into(),
+            targets: Vec::new(),
+            chunk_size: None,
+            is_responsive: true,
+        }
+    }
+
+    /// Offers the selection as `target`, like `UTF8_STRING`, with the given
+    /// value.
+    ///
+    /// The value is stored with the target itself as its type.
+    pub fn target(
+        mut self,
+        target: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.targets.push((target.into(), data.into()));
+        self
+    }
+
+    /// Sends values larger than `chunk_size` bytes incrementally, in chunks
+    /// of that size, instead of at once.
+    pub fn incr(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Never answers conversions, so that requestors time out.
+    ///
+    /// Requests are still recorded.
+    pub fn unresponsive(mut self) -> Self {
+        self.is_responsive = false;
+        self
+    }
+
+    /// Connects to `display` and takes ownership of the selection.
+    ///
+    /// Returns once the selection is owned. It stays so until another
+    /// client takes it, or the returned [`OwnerHandle`] is dropped.
+    pub fn spawn(self, display: &str) -> Result<OwnerHandle, Error> {
+        let (connection, screen) = RustConnection::connect(Some(display))?;
+        let window = connection.generate_id()?;
+
+        {
+            let screen = connection.setup().roots.get(screen).ok_or(
+                Error::ConnectionFailed(
+                    x11rb::errors::ConnectError::InvalidScreen,
+                ),
+            )?;
+
+            xproto::create_window(
+                &connection,
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                screen.root_visual,
+                &CreateWindowAux::new().event_mask(
+                    EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE,
+                ),
+            )?
+            .check()?;
+        }
+
+        let selection = intern(&connection, &self.selection)?;
+
+        let atoms = Atoms {
+            targets: intern(&connection, "TARGETS")?,
+            incr: intern(&connection, "INCR")?,
+        };
+
+        let mut targets = Vec::with_capacity(self.targets.len());
+
+        for (name, data) in self.targets {
+            targets.push((intern(&connection, &name)?, data));
+        }
+
+        xproto::set_selection_owner(
+            &connection,
+            window,
+            selection,
+            x11rb::CURRENT_TIME,
+        )?;
+
+        let owner =
+            xproto::get_selection_owner(&connection, selection)?.reply()?;
+
+        if owner.owner != window {
+            return Err(Error::OwnershipRefused);
+        }
+
+        let connection = Arc::new(connection);
+        let shared = Arc::new(Mutex::new(Shared {
+            requests: Vec::new(),
+            is_owner: true,
+        }));
+
+        let mut worker = Worker {
+            connection: Arc::clone(&connection),
+            window,
+            atoms,
+            targets,
+            chunk_size: self.chunk_size,
+            is_responsive: self.is_responsive,
+            shared: Arc::clone(&shared),
+            transfers: Vec::new(),
+        };
+
+        let thread = thread::spawn(move || worker.run());
+
+        Ok(OwnerHandle {
+            connection,
+            window,
+            shared,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// A running [`SelectionOwner`].
+///
+/// Dropping it destroys its window, which releases the selection.
+#[derive(Debug)]
+pub struct OwnerHandle {
+    connection: Arc<RustConnection>,
+    window: Window,
+    shared: Arc<Mutex<Shared>>,
+    thread: Option<thread::JoinHandle<Result<(), Error>>>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// The names of the targets requested so far, in order.
+    requests: Vec<String>,
+    is_owner: bool,
+}
+
+impl OwnerHandle {
+    /// Returns the names of the targets requested so far, in order,
+    /// including `TARGETS`.
+    pub fn requests(&self) -> Vec<String> {
+        self.lock().requests.clone()
+    }
+
+    /// Returns `true` until another client takes the selection.
+    pub fn is_owner(&self) -> bool {
+        self.lock().is_owner
+    }
+
+    /// Releases the selection and waits for the owner to stop, returning
+    /// the error that stopped it early, if any.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+
+        // The worker stops once it learns its window is gone.
+        let _ = xproto::destroy_window(&*self.connection, self.window);
+        let _ = self.connection.flush();
+
+        thread.join().map_err(|_| Error::ThreadPanicked)?
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for OwnerHandle {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Atoms {
+    targets: Atom,
+    incr: Atom,
+}
+
+/// An incremental transfer in progress.
+struct Transfer {
+    requestor: Window,
+    property: Atom,
+    kind: Atom,
+    data: Vec<u8>,
+    /// How much of `data` was sent already.
+    sent: usize,
+}
+
+/// Answers conversions of the selection.
+struct Worker {
+    connection: Arc<RustConnection>,
+    window: Window,
+    atoms: Atoms,
+    targets: Vec<(Atom, Vec<u8>)>,
+    chunk_size: Option<usize>,
+    is_responsive: bool,
+    shared: Arc<Mutex<Shared>>,
+    transfers: Vec<Transfer>,
+}
+
+impl Worker {
+    fn run(&mut self) -> Result<(), Error> {
+        loop {
+            match self.connection.wait_for_event()? {
+                Event::SelectionRequest(event) => {
+                    let target = atom_name(&self.connection, event.target)?;
+                    self.lock().requests.push(target);
+
+                    if self.is_responsive {
+                        self.answer(&event)?;
+                    }
+                }
+                Event::SelectionClear(event) if event.owner == self.window => {
+                    self.lock().is_owner = false;
+                }
+                Event::PropertyNotify(event)
+                    if event.state == Property::DELETE =>
+                {
+                    self.send_chunk(event.window, event.atom)?;
+                }
+                Event::DestroyNotify(event) if event.window == self.window => {
+                    self.lock().is_owner = false;
+
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn answer(&mut self, event: &SelectionRequestEvent) -> Result<(), Error> {
+        // Obsolete clients do not name a property, and expect the value to
+        // be stored in the one named after the target.
+        let property = if event.property == x11rb::NONE {
+            event.target
+        } else {
+            event.property
+        };
+
+        let value = self
+            .targets
+            .iter()
+            .find(|(target, _)| *target == event.target)
+            .map(|(target, data)| (*target, data.clone()));
+
+        let converted = if event.target == self.atoms.targets {
+            let mut targets = vec![self.atoms.targets];
+            targets.extend(self.targets.iter().map(|(target, _)| *target));
+
+            self.connection.change_property32(
+                PropMode::REPLACE,
+                event.requestor,
+                property,
+                AtomEnum::ATOM,
+                &targets,
+            )?;
+
+            true
+        } else if let Some((kind, data)) = value {
+            match self.chunk_size {
+                Some(chunk_size) if data.len() > chunk_size => {
+                    self.start_transfer(event.requestor, property, kind, data)?
+                }
+                _ => {
+                    self.connection.change_property8(
+                        PropMode::REPLACE,
+                        event.requestor,
+                        property,
+                        kind,
+                        &data,
+                    )?;
+                }
+            }
+
+            true
+        } else {
+            false
+        };
+
+        xproto::send_event(
+            &*self.connection,
+            false,
+            event.requestor,
+            EventMask::NO_EVENT,
+            xproto::SelectionNotifyEvent {
+                response_type: xproto::SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: event.time,
+                requestor: event.requestor,
+                selection: event.selection,
+                target: event.target,
+                property: if converted { property } else { x11rb::NONE },
+            },
+        )?;
+
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    /// Announce the size of `data`, to be sent in chunks every time the
+    /// requestor deletes `property`.
+    fn start_transfer(
+        &mut self,
+        requestor: Window,
+        property: Atom,
+        kind: Atom,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        // Deletions are how the requestor asks for the next chunk.
+        xproto::change_window_attributes(
+            &*self.connection,
+            requestor,
+            &ChangeWindowAttributesAux::new()
+                .event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+
+        let size = u32::try_from(data.len()).unwrap_or(u32::MAX);
+
+        self.connection.change_property32(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            self.atoms.incr,
+            &[size],
+        )?;
+
+        self.transfers.push(Transfer {
+            requestor,
+            property,
+            kind,
+            data,
+            sent: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Send the next chunk of the transfer to `property` of `requestor`, if
+    /// there is one.
+    ///
+    /// The transfer ends with an empty chunk.
+    fn send_chunk(
+        &mut self,
+        requestor: Window,
+        property: Atom,
+    ) -> Result<(), Error> {
+        let index = match self.transfers.iter().position(|transfer| {
+            transfer.requestor == requestor && transfer.property == property
+        }) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+
+        let chunk_size = self.chunk_size.unwrap_or(usize::MAX);
+        let transfer = &mut self.transfers[index];
+
+        let start = transfer.sent;
+        let end = transfer.data.len().min(start.saturating_add(chunk_size));
+
+        self.connection.change_property8(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.kind,
+            &transfer.data[start..end],
+        )?;
+
+        if start == end {
+            let _ = self.transfers.remove(index);
+        } else {
+            transfer.sent = end;
+        }
+
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,335 @@
+use crate::{atom_name, intern, Error};
+
+use rustix::event::{self, PollFd, PollFlags, Timespec};
+use rustix::io::Errno;
+use x11rb::connection::Connection as _;
+use x11rb::errors::ConnectError;
+use x11rb::protocol::xproto::{
+    self, Atom, AtomEnum, CreateWindowAux, EventMask, GetPropertyReply,
+    PropMode, Property, Window, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Asks for the values of selections on behalf of a foreign application.
+#[derive(Debug)]
+pub struct Requestor {
+    connection: RustConnection,
+    window: Window,
+    /// The property values are stored in.
+    property: Atom,
+    incr: Atom,
+    timeout: Duration,
+}
+
+/// The value of a selection, as converted by its owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    /// The name of the type of the value.
+    pub kind: String,
+    /// The format of the value: 8, 16 or 32 bits per item.
+    pub format: u8,
+    pub data: Vec<u8>,
+    /// Whether the value was sent incrementally.
+    pub is_incr: bool,
+}
+
+impl Requestor {
+    /// Connects to `display`.
+    ///
+    /// Owners are given 3 seconds to answer each conversion, unless
+    /// [`Requestor::timeout`] says otherwise.
+    pub fn connect(display: &str) -> Result<Self, Error> {
+        let (connection, screen) = RustConnection::connect(Some(display))?;
+        let window = connection.generate_id()?;
+
+        {
+            let screen =
+                connection.setup().roots.get(screen).ok_or(
+                    Error::ConnectionFailed(ConnectError::InvalidScreen),
+                )?;
+
+            xproto::create_window(
+                &connection,
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                screen.root_visual,
+                &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )?
+            .check()?;
+        }
+
+        let property = intern(&connection, "CLIPBOARD_TESTING")?;
+        let incr = intern(&connection, "INCR")?;
+
+        Ok(Requestor {
+            connection,
+            window,
+            property,
+            incr,
+            timeout: Duration::from_secs(3),
+        })
+    }
+
+    /// Sets how long owners are given to answer, and to send each chunk of
+    /// incremental transfers, before [`Error::Timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Asks the owner of `selection` to convert it to `target`.
+    ///
+    /// Returns `None` if there is no owner, or it refuses the conversion.
+    pub fn convert(
+        &self,
+        selection: &str,
+        target: &str,
+    ) -> Result<Option<Value>, Error> {
+        let selection = intern(&self.connection, selection)?;
+        let target = intern(&self.connection, target)?;
+
+        xproto::delete_property(&self.connection, self.window, self.property)?;
+
+        let property = self.request(selection, target)?;
+
+        if property == x11rb::NONE {
+            return Ok(None);
+        }
+
+        let reply = self.take_property(property)?;
+
+        if reply.type_ != self.incr {
+            return Ok(Some(Value {
+                kind: atom_name(&self.connection, reply.type_)?,
+                format: reply.format,
+                data: reply.value,
+                is_incr: false,
+            }));
+        }
+
+        // Deleting the property above asked for the first chunk.
+        let mut data = Vec::new();
+        let mut first = None;
+
+        let last = loop {
+            self.wait_for(|event| match event {
+                Event::PropertyNotify(event)
+                    if event.window == self.window
+                        && event.atom == property
+                        && event.state == Property::NEW_VALUE =>
+                {
+                    Some(())
+                }
+                _ => None,
+            })?;
+
+            let reply = self.take_property(property)?;
+
+            if reply.value.is_empty() {
+                break reply;
+            }
+
+            data.extend_from_slice(&reply.value);
+            let _ = first.get_or_insert((reply.type_, reply.format));
+        };
+
+        let (kind, format) = first.unwrap_or((last.type_, last.format));
+
+        Ok(Some(Value {
+            kind: atom_name(&self.connection, kind)?,
+            format,
+            data,
+            is_incr: true,
+        }))
+    }
+
+    /// Asks the owner of `selection` for the names of the targets it can be
+    /// converted to.
+    ///
+    /// Returns `None` if there is no owner, or it refuses the conversion.
+    pub fn targets(
+        &self,
+        selection: &str,
+    ) -> Result<Option<Vec<String>>, Error> {
+        let value = match self.convert(selection, "TARGETS")? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let mut targets = Vec::new();
+
+        for atom in value.data.chunks_exact(4) {
+            let atom = u32::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]);
+
+            targets.push(atom_name(&self.connection, atom)?);
+        }
+
+        Ok(Some(targets))
+    }
+
+    /// Asks the owner of `selection` to convert it to each of `targets` at
+    /// once, through a `MULTIPLE` conversion.
+    ///
+    /// Returns `None` if there is no owner, or it refuses the conversion.
+    /// Otherwise, returns the value of each target, in order, or `None` for
+    /// the ones the owner refused.
+    ///
+    /// Values are not read incrementally: those sent so are returned as
+    /// their `INCR` announcement.
+    pub fn convert_multiple(
+        &self,
+        selection: &str,
+        targets: &[&str],
+    ) -> Result<Option<Vec<Option<Value>>>, Error> {
+        let selection = intern(&self.connection, selection)?;
+        let multiple = intern(&self.connection, "MULTIPLE")?;
+        let atom_pair = intern(&self.connection, "ATOM_PAIR")?;
+
+        let mut pairs = Vec::with_capacity(targets.len() * 2);
+
+        for (index, target) in targets.iter().enumerate() {
+            let property = intern(
+                &self.connection,
+                &format!("CLIPBOARD_TESTING_{}", index),
+            )?;
+
+            xproto::delete_property(&self.connection, self.window, property)?;
+
+            pairs.push(intern(&self.connection, target)?);
+            pairs.push(property);
+        }
+
+        self.connection.change_property32(
+            PropMode::REPLACE,
+            self.window,
+            self.property,
+            atom_pair,
+            &pairs,
+        )?;
+
+        let property = self.request(selection, multiple)?;
+
+        if property == x11rb::NONE {
+            return Ok(None);
+        }
+
+        // Refused conversions have their property replaced with `None`.
+        let pairs: Vec<Atom> = match self.take_property(property)?.value32() {
+            Some(pairs) => pairs.collect(),
+            None => return Ok(None),
+        };
+
+        let mut values = Vec::with_capacity(targets.len());
+
+        for pair in pairs.chunks_exact(2) {
+            if pair[1] == x11rb::NONE {
+                values.push(None);
+                continue;
+            }
+
+            let reply = self.take_property(pair[1])?;
+
+            values.push(Some(Value {
+                kind: atom_name(&self.connection, reply.type_)?,
+                format: reply.format,
+                data: reply.value,
+                is_incr: false,
+            }));
+        }
+
+        Ok(Some(values))
+    }
+
+    /// Ask the owner of `selection` to convert it to `target`, in
+    /// our property, and wait for its answer.
+    ///
+    /// Returns the property the value was stored in, or `NONE` if the owner
+    /// refused the conversion.
+    fn request(&self, selection: Atom, target: Atom) -> Result<Atom, Error> {
+        xproto::convert_selection(
+            &self.connection,
+            self.window,
+            selection,
+            target,
+            self.property,
+            x11rb::CURRENT_TIME,
+        )?;
+        self.connection.flush()?;
+
+        let notify = self.wait_for(|event| match event {
+            Event::SelectionNotify(event)
+                if event.requestor == self.window
+                    && event.selection == selection
+                    && event.target == target =>
+            {
+                Some(*event)
+            }
+            _ => None,
+        })?;
+
+        Ok(notify.property)
+    }
+
+    /// Read and delete `property` of our window.
+    fn take_property(&self, property: Atom) -> Result<GetPropertyReply, Error> {
+        Ok(xproto::get_property(
+            &self.connection,
+            true,
+            self.window,
+            property,
+            AtomEnum::ANY,
+            0,
+            u32::MAX,
+        )?
+        .reply()?)
+    }
+
+    /// Wait for an event `filter` maps to a value, for at most the timeout.
+    fn wait_for<T>(
+        &self,
+        mut filter: impl FnMut(&Event) -> Option<T>,
+    ) -> Result<T, Error> {
+        let deadline = Instant::now() + self.timeout;
+
+        loop {
+            while let Some(event) = self.connection.poll_for_event()? {
+                if let Some(value) = filter(&event) {
+                    return Ok(value);
+                }
+            }
+
+            let timeout = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|timeout| *timeout > Duration::ZERO)
+                .ok_or(Error::Timeout)?;
+
+            let timeout = Timespec::try_from(timeout).ok();
+            let mut fds =
+                [PollFd::new(self.connection.stream(), PollFlags::IN)];
+
+            match event::poll(&mut fds, timeout.as_ref()) {
+                Ok(_) | Err(Errno::INTR) => {}
+                Err(error) => return Err(Error::Io(error.into())),
+            }
+        }
+    }
+}
+
+impl Drop for Requestor {
+    fn drop(&mut self) {
+        let _ = xproto::destroy_window(&self.connection, self.window);
+        let _ = self.connection.flush();
+    }
+}
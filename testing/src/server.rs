@@ -0,0 +1,75 @@
+use crate::{spawn_error, stop, Error, STARTUP_TIMEOUT};
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+/// A private Xvfb server, killed when dropped.
+#[derive(Debug)]
+pub struct XServer {
+    process: Child,
+    display: String,
+}
+
+impl XServer {
+    /// Starts Xvfb on a free display, and waits until it accepts
+    /// connections.
+    ///
+    /// Fails with [`Error::NotInstalled`] if Xvfb cannot be found, so tests
+    /// can be skipped where it is missing.
+    pub fn start() -> Result<Self, Error> {
+        // Xvfb picks a free display by itself, and reports it once it is
+        // ready for clients.
+        let mut process = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-nolisten", "tcp", "-noreset"])
+            .args(["-screen", "0", "640x480x24"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|error| spawn_error("Xvfb", error))?;
+
+        let (sender, receiver) = mpsc::channel();
+        let stdout = process.stdout.take();
+
+        let _ = thread::spawn(move || {
+            let mut line = String::new();
+
+            if let Some(stdout) = stdout {
+                let _ = BufReader::new(stdout).read_line(&mut line);
+            }
+
+            let _ = sender.send(line);
+        });
+
+        let number = receiver
+            .recv_timeout(STARTUP_TIMEOUT)
+            .ok()
+            .and_then(|line| line.trim().parse::<u32>().ok());
+
+        match number {
+            Some(number) => Ok(XServer {
+                process,
+                display: format!(":{}", number),
+            }),
+            None => {
+                stop(&mut process);
+
+                Err(Error::StartupFailed("Xvfb"))
+            }
+        }
+    }
+
+    /// The name of the display, like `:1`, to connect to or to set as
+    /// `$DISPLAY`.
+    pub fn display(&self) -> &str {
+        &self.display
+    }
+}
+
+impl Drop for XServer {
+    fn drop(&mut self) {
+        stop(&mut self.process);
+    }
+}
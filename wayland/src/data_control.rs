@@ -0,0 +1,375 @@
+//! Clipboard access through the data control protocols.
+//!
+//! Unlike data devices, data control devices let privileged clients, like
+//! clipboard managers, read and set selections without keyboard focus.
+//! Both the standard `ext-data-control-v1` protocol and its wlroots
+//! predecessor are supported, as compositors may advertise either.
+use sctk::data_device_manager::{ReadPipe, WritePipe};
+use sctk::reexports::client::globals::GlobalList;
+use sctk::reexports::client::protocol::wl_seat::WlSeat;
+use sctk::reexports::client::{
+    event_created_child, Connection, Dispatch, Proxy, QueueHandle,
+};
+use sctk::reexports::protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+    ext_data_control_manager_v1::{self, ExtDataControlManagerV1},
+    ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+    ext_data_control_source_v1::{self, ExtDataControlSourceV1},
+};
+use sctk::reexports::protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::{self, ZwlrDataControlManagerV1},
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+use wayland_backend::client::ObjectId;
+
+use crate::state::State;
+use crate::Selection;
+
+use std::io;
+use std::os::unix::io::{AsFd, FromRawFd, OwnedFd};
+use std::sync::Mutex;
+
+/// Runs `$body` with `$proxy` bound to the protocol object, whatever the
+/// protocol.
+macro_rules! forward {
+    ($value:expr, $proxy:ident => $body:expr) => {
+        match $value {
+            Protocol::Ext($proxy) => $body,
+            Protocol::Wlr($proxy) => $body,
+        }
+    };
+}
+
+/// An object of either data control protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Protocol<E, W> {
+    Ext(E),
+    Wlr(W),
+}
+
+/// Creates data control devices and sources.
+#[derive(Debug)]
+pub struct Manager(Protocol<ExtDataControlManagerV1, ZwlrDataControlManagerV1>);
+
+impl Manager {
+    /// Bind the data control manager advertised by the compositor, if any,
+    /// preferring the standard protocol.
+    pub fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Option<Self> {
+        if let Ok(manager) = globals.bind(queue_handle, 1..=1, ()) {
+            return Some(Manager(Protocol::Ext(manager)));
+        }
+
+        globals
+            .bind(queue_handle, 1..=2, ())
+            .ok()
+            .map(|manager| Manager(Protocol::Wlr(manager)))
+    }
+
+    /// Returns `true` if devices can set the primary selection, which the
+    /// first version of the wlroots protocol cannot.
+    pub fn supports_primary(&self) -> bool {
+        match &self.0 {
+            Protocol::Ext(_) => true,
+            Protocol::Wlr(manager) => manager.version()
+                >= zwlr_data_control_device_v1::REQ_SET_PRIMARY_SELECTION_SINCE,
+        }
+    }
+
+    /// Create the device controlling the selections of `seat`.
+    pub fn get_device(
+        &self,
+        seat: &WlSeat,
+        queue_handle: &QueueHandle<State>,
+    ) -> Device {
+        let device =
+            match &self.0 {
+                Protocol::Ext(manager) => Protocol::Ext(
+                    manager.get_data_device(seat, queue_handle, seat.id()),
+                ),
+                Protocol::Wlr(manager) => Protocol::Wlr(
+                    manager.get_data_device(seat, queue_handle, seat.id()),
+                ),
+            };
+
+        Device {
+            device,
+            selection: None,
+            primary_selection: None,
+        }
+    }
+
+    /// Create a source offering `mimes`, to be set as the `target`
+    /// selection.
+    pub fn create_source(
+        &self,
+        target: Selection,
+        mimes: &[String],
+        queue_handle: &QueueHandle<State>,
+    ) -> Source {
+        let source = match &self.0 {
+            Protocol::Ext(manager) => {
+                Protocol::Ext(manager.create_data_source(queue_handle, target))
+            }
+            Protocol::Wlr(manager) => {
+                Protocol::Wlr(manager.create_data_source(queue_handle, target))
+            }
+        };
+
+        for mime in mimes {
+            forward!(&source, source => source.offer(mime.clone()));
+        }
+
+        Source(source)
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        forward!(&self.0, manager => manager.destroy());
+    }
+}
+
+/// Controls the selections of a seat, and keeps track of their offers.
+#[derive(Debug)]
+pub struct Device {
+    device: Protocol<ExtDataControlDeviceV1, ZwlrDataControlDeviceV1>,
+    selection: Option<Offer>,
+    primary_selection: Option<Offer>,
+}
+
+impl Device {
+    /// The offer of the `target` selection, if it is not empty.
+    pub fn offer(&self, target: Selection) -> Option<&Offer> {
+        match target {
+            Selection::Clipboard => self.selection.as_ref(),
+            Selection::Primary => self.primary_selection.as_ref(),
+        }
+    }
+
+    /// Replace the offer of the `target` selection.
+    pub fn set_offer(&mut self, target: Selection, offer: Option<Offer>) {
+        match target {
+            Selection::Clipboard => self.selection = offer,
+            Selection::Primary => self.primary_selection = offer,
+        }
+    }
+
+    /// Set `source` as the `target` selection.
+    pub fn set_selection(&self, target: Selection, source: &Source) {
+        match (&self.device, &source.0, target) {
+            (
+                Protocol::Ext(device),
+                Protocol::Ext(source),
+                Selection::Clipboard,
+            ) => device.set_selection(Some(source)),
+            (
+                Protocol::Ext(device),
+                Protocol::Ext(source),
+                Selection::Primary,
+            ) => device.set_primary_selection(Some(source)),
+            (
+                Protocol::Wlr(device),
+                Protocol::Wlr(source),
+                Selection::Clipboard,
+            ) => device.set_selection(Some(source)),
+            (
+                Protocol::Wlr(device),
+                Protocol::Wlr(source),
+                Selection::Primary,
+            ) => device.set_primary_selection(Some(source)),
+            // Devices and sources come from the same manager.
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        forward!(&self.device, device => device.destroy());
+    }
+}
+
+/// Offers a value we set as a selection.
+#[derive(Debug)]
+pub struct Source(Protocol<ExtDataControlSourceV1, ZwlrDataControlSourceV1>);
+
+impl Source {
+    /// Returns `true` if this is the given protocol object.
+    pub fn is(&self, id: &ObjectId) -> bool {
+        forward!(&self.0, source => &source.id() == id)
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        forward!(&self.0, source => source.destroy());
+    }
+}
+
+/// The value of a selection, as offered by its owner.
+#[derive(Debug)]
+pub struct Offer(Protocol<ExtDataControlOfferV1, ZwlrDataControlOfferV1>);
+
+/// The mime types of an [`Offer`], announced after its creation.
+type OfferData = Mutex<Vec<String>>;
+
+impl Offer {
+    /// The mime types the value is offered as.
+    pub fn mime_types(&self) -> Vec<String> {
+        let mimes = forward!(&self.0, offer => offer.data::<OfferData>());
+
+        mimes
+            .and_then(|mimes| mimes.lock().ok().map(|mimes| mimes.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Ask the owner to send the value as `mime`, returning the pipe it is
+    /// sent through.
+    pub fn receive(&self, mime: String) -> io::Result<ReadPipe> {
+        let mut fds = [0; 2];
+
+        // Safety: `fds` has room for both ends of the pipe.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Safety: both ends were just opened, and are owned by nobody else.
+        let (read, write) = unsafe {
+            (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+        };
+
+        forward!(&self.0, offer => offer.receive(mime, write.as_fd()));
+
+        // Our end of the pipe is closed once the request is sent, so that
+        // the owner closing its end is noticed.
+        drop(write);
+
+        Ok(ReadPipe::from(read))
+    }
+}
+
+impl Drop for Offer {
+    fn drop(&mut self) {
+        forward!(&self.0, offer => offer.destroy());
+    }
+}
+
+/// Implements the handlers of the objects of a data control protocol.
+macro_rules! dispatch {
+    (
+        $variant:ident,
+        $manager:ident::$Manager:ident,
+        $device:ident::$Device:ident,
+        $source:ident::$Source:ident,
+        $offer:ident::$Offer:ident
+    ) => {
+        impl Dispatch<$Manager, ()> for State {
+            fn event(
+                _: &mut State,
+                _: &$Manager,
+                _: $manager::Event,
+                _: &(),
+                _: &Connection,
+                _: &QueueHandle<State>,
+            ) {
+            }
+        }
+
+        impl Dispatch<$Device, ObjectId> for State {
+            fn event(
+                state: &mut State,
+                _: &$Device,
+                event: $device::Event,
+                seat: &ObjectId,
+                _: &Connection,
+                _: &QueueHandle<State>,
+            ) {
+                let offer =
+                    |offer: Option<$Offer>| offer.map(Protocol::$variant);
+
+                match event {
+                    $device::Event::Selection { id } => state
+                        .control_offer(
+                            seat,
+                            Selection::Clipboard,
+                            offer(id).map(Offer),
+                        ),
+                    $device::Event::PrimarySelection { id } => state
+                        .control_offer(
+                            seat,
+                            Selection::Primary,
+                            offer(id).map(Offer),
+                        ),
+                    // The device is no longer valid.
+                    $device::Event::Finished => {
+                        state.remove_control_device(seat);
+                    }
+                    _ => {}
+                }
+            }
+
+            event_created_child!(State, $Device, [
+                $device::EVT_DATA_OFFER_OPCODE => ($Offer, OfferData::default())
+            ]);
+        }
+
+        impl Dispatch<$Source, Selection> for State {
+            fn event(
+                state: &mut State,
+                source: &$Source,
+                event: $source::Event,
+                target: &Selection,
+                _: &Connection,
+                _: &QueueHandle<State>,
+            ) {
+                match event {
+                    $source::Event::Send { mime_type, fd } => {
+                        state.send(*target, WritePipe::from(fd), mime_type);
+                    }
+                    $source::Event::Cancelled => {
+                        state.control_cancelled(*target, &source.id());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        impl Dispatch<$Offer, OfferData> for State {
+            fn event(
+                _: &mut State,
+                _: &$Offer,
+                event: $offer::Event,
+                mimes: &OfferData,
+                _: &Connection,
+                _: &QueueHandle<State>,
+            ) {
+                if let $offer::Event::Offer { mime_type } = event {
+                    if let Ok(mut mimes) = mimes.lock() {
+                        mimes.push(mime_type);
+                    }
+                }
+            }
+        }
+    };
+}
+
+dispatch!(
+    Ext,
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    ext_data_control_device_v1::ExtDataControlDeviceV1,
+    ext_data_control_source_v1::ExtDataControlSourceV1,
+    ext_data_control_offer_v1::ExtDataControlOfferV1
+);
+
+dispatch!(
+    Wlr,
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+    zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1
+);
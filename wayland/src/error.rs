@@ -0,0 +1,32 @@
+use sctk::reexports::client::ConnectError;
+
+use std::io;
+
+#[must_use]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to connect to the compositor: {0}")]
+    ConnectionFailed(#[from] ConnectError),
+    #[error("no events received on any seat")]
+    NoSeat,
+    #[error("client doesn't have focus")]
+    NoFocus,
+    #[error("selection is empty")]
+    Empty,
+    #[error("none of the requested mime types is offered: {0:?}")]
+    NoMimeType(Vec<String>),
+    #[error("offer is not ready yet")]
+    NotReady,
+    #[error("{0} is not supported by the compositor")]
+    Unsupported(&'static str),
+    #[error("timeout")]
+    Timeout,
+    #[error("transfer exceeds the maximum size of {0} bytes")]
+    TooLarge(usize),
+    #[error("invalid utf8 string: {0}")]
+    InvalidUtf8(std::string::FromUtf8Error),
+    #[error("clipboard worker is dead")]
+    WorkerDied,
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
@@ -0,0 +1,22 @@
+/// A Wayland selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The clipboard, used by explicit copy and paste.
+    Clipboard,
+    /// The primary selection, holding the latest selected text.
+    Primary,
+}
+
+/// A change of the contents or ownership of a [`Selection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardEvent {
+    /// The selection has new contents, which may be our own.
+    Changed(Selection),
+    /// Another client replaced a selection we offered.
+    OwnershipLost(Selection),
+}
+
+/// A callback notified of every [`ClipboardEvent`].
+///
+/// It is unsubscribed as soon as it returns `false`.
+pub type Subscriber = Box<dyn FnMut(ClipboardEvent) -> bool + Send>;
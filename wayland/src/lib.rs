@@ -11,41 +11,460 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+mod data_control;
+mod error;
+mod event;
+mod settings;
+mod state;
+mod worker;
+
+pub use error::Error;
+pub use event::{ClipboardEvent, Selection, Subscriber};
+pub use settings::Settings;
+
+use worker::{Command, Data, Loaded};
+
+use futures_channel::oneshot;
+use sctk::reexports::calloop::channel;
+use sctk::reexports::client::backend::Backend;
+use sctk::reexports::client::Connection;
 
-use std::error::Error;
 use std::ffi::c_void;
-use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{mpsc, Arc};
+use std::task::{self, Poll, Wake, Waker};
+use std::thread;
+
+/// The error a lazy data provider fails with.
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The mime types used to exchange text, in order of preference.
+const TEXT_MIME_TYPES: [&str; 3] =
+    ["text/plain;charset=utf-8", "UTF8_STRING", "text/plain"];
 
 pub struct Clipboard {
-    context: Arc<Mutex<smithay_clipboard::Clipboard>>,
+    commands: channel::Sender<Command>,
+    worker: Option<thread::JoinHandle<()>>,
+    /// Whether the compositor supports data control, if we waited for the
+    /// worker to find out.
+    has_data_control: Option<bool>,
 }
 
 impl Clipboard {
+    /// Connect to the clipboard of the given Wayland display.
+    ///
+    /// Selections are only available while one of our surfaces has keyboard
+    /// focus, unless the compositor advertises the `ext-data-control-v1` or
+    /// `wlr-data-control-unstable-v1` protocol, which is then used instead.
+    ///
+    /// # Safety
+    /// `display` must be a valid `*mut wl_display` pointer that stays valid
+    /// for as long as the [`Clipboard`] is alive.
     pub unsafe fn connect(display: *mut c_void) -> Clipboard {
-        let context = Arc::new(Mutex::new(smithay_clipboard::Clipboard::new(
-            display as *mut _,
-        )));
+        Self::connect_with(display, Settings::default())
+    }
+
+    /// Connect to the clipboard of the given Wayland display with the given
+    /// [`Settings`].
+    ///
+    /// # Safety
+    /// See [`Clipboard::connect`].
+    pub unsafe fn connect_with(
+        display: *mut c_void,
+        settings: Settings,
+    ) -> Clipboard {
+        let backend = Backend::from_foreign_display(display as *mut _);
+        let connection = Connection::from_backend(backend);
+
+        let (commands, receiver) = channel::channel();
+        let (started, _) = oneshot::channel();
+        let worker = worker::spawn(connection, receiver, settings, started);
+
+        Clipboard {
+            commands,
+            worker,
+            has_data_control: None,
+        }
+    }
+
+    /// Connect to the compositor named by the environment, like
+    /// `$WAYLAND_DISPLAY`, without a window of our own.
+    ///
+    /// Without keyboard focus, selections are only available if the
+    /// compositor supports data control, which
+    /// [`Clipboard::has_data_control`] tells.
+    ///
+    /// Fails with [`Error::WorkerDied`] if the compositor offers no
+    /// clipboard at all.
+    pub fn connect_to_env() -> Result<Clipboard, Error> {
+        Self::connect_to_env_with(Settings::default())
+    }
+
+    /// Like [`Clipboard::connect_to_env`], with the given [`Settings`].
+    pub fn connect_to_env_with(settings: Settings) -> Result<Clipboard, Error> {
+        let connection = Connection::connect_to_env()?;
 
-        Clipboard { context }
+        let (commands, receiver) = channel::channel();
+        let (started, startup) = oneshot::channel();
+        let worker = worker::spawn(connection, receiver, settings, started);
+
+        let has_data_control =
+            block_on(startup).map_err(|_| Error::WorkerDied)?;
+
+        Ok(Clipboard {
+            commands,
+            worker,
+            has_data_control: Some(has_data_control),
+        })
+    }
+
+    /// Returns whether the compositor supports data control, letting us
+    /// access selections without keyboard focus.
+    ///
+    /// Only known for connections made with [`Clipboard::connect_to_env`],
+    /// and `None` otherwise.
+    pub fn has_data_control(&self) -> Option<bool> {
+        self.has_data_control
     }
 
-    pub fn read(&self) -> Result<String, Box<dyn Error>> {
-        Ok(self.context.lock().unwrap().load()?)
+    pub fn read(&self) -> Result<String, Error> {
+        block_on(self.read_async())
     }
 
-    pub fn read_primary(&self) -> Result<String, Box<dyn Error>> {
-        Ok(self.context.lock().unwrap().load_primary()?)
+    pub fn read_primary(&self) -> Result<String, Error> {
+        block_on(self.read_primary_async())
     }
 
-    pub fn write(&mut self, data: String) -> Result<(), Box<dyn Error>> {
-        self.context.lock().unwrap().store(data);
+    /// Read the clipboard without blocking.
+    ///
+    /// The returned future completes once the whole value has been received.
+    /// Dropping it abandons the transfer.
+    pub fn read_async(
+        &self,
+    ) -> impl Future<Output = Result<String, Error>> + Send + 'static {
+        self.read_text(Selection::Clipboard)
+    }
+
+    /// Read the primary selection without blocking.
+    ///
+    /// The returned future completes once the whole value has been received.
+    /// Dropping it abandons the transfer.
+    pub fn read_primary_async(
+        &self,
+    ) -> impl Future<Output = Result<String, Error>> + Send + 'static {
+        self.read_text(Selection::Primary)
+    }
+
+    /// Read the first of the given mime types that is offered on the
+    /// clipboard, returning the chosen mime type along with its data.
+    pub fn read_mime(
+        &self,
+        mimes: &[&str],
+    ) -> Result<(String, Vec<u8>), Error> {
+        block_on(self.read_mime_async(mimes))
+    }
+
+    /// Like [`Clipboard::read_mime`], but without blocking.
+    ///
+    /// Dropping the returned future abandons the transfer.
+    pub fn read_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> impl Future<Output = Result<(String, Vec<u8>), Error>> + Send + 'static
+    {
+        let loaded = self.load(Selection::Clipboard, mimes);
+
+        async move { loaded?.await.map_err(|_| Error::WorkerDied)? }
+    }
+
+    /// List the mime types offered on the clipboard.
+    pub fn available_formats(&self) -> Result<Vec<String>, Error> {
+        self.formats(Selection::Clipboard)
+    }
+
+    /// List the mime types offered on the primary selection.
+    pub fn available_primary_formats(&self) -> Result<Vec<String>, Error> {
+        self.formats(Selection::Primary)
+    }
+
+    /// Subscribe to changes of the clipboard and the primary selection.
+    ///
+    /// The `subscriber` is called from the worker thread until it returns
+    /// `false`.
+    pub fn subscribe(
+        &self,
+        subscriber: impl FnMut(ClipboardEvent) -> bool + Send + 'static,
+    ) -> Result<(), Error> {
+        self.commands
+            .send(Command::Subscribe(Box::new(subscriber)))
+            .map_err(|_| Error::WorkerDied)?;
 
         Ok(())
     }
 
-    pub fn write_primary(&mut self, data: String) -> Result<(), Box<dyn Error>> {
-        self.context.lock().unwrap().store_primary(data);
+    pub fn write(&mut self, data: String) -> Result<(), Error> {
+        // Unlike `write_async`, we do not wait for the outcome.
+        drop(self.write_text(Selection::Clipboard, data, None)?);
 
         Ok(())
     }
+
+    /// Write to the clipboard with the serial of the input event that
+    /// caused it.
+    ///
+    /// Compositors ignore selections set with outdated serials, so this is
+    /// more reliable than [`Clipboard::write`], which uses the serial of the
+    /// latest input event we received.
+    pub fn write_at(&mut self, data: String, serial: u32) -> Result<(), Error> {
+        drop(self.write_text(Selection::Clipboard, data, Some(serial))?);
+
+        Ok(())
+    }
+
+    pub fn write_primary(&mut self, data: String) -> Result<(), Error> {
+        drop(self.write_text(Selection::Primary, data, None)?);
+
+        Ok(())
+    }
+
+    /// Write to the clipboard, returning a future that resolves once the
+    /// value is offered.
+    ///
+    /// Unlike [`Clipboard::write`], the future reports when nothing could be
+    /// offered, for instance because none of our surfaces has focus and data
+    /// control is not available.
+    pub fn write_async(
+        &mut self,
+        data: String,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let stored = self.write_text(Selection::Clipboard, data, None);
+
+        async move { stored?.await.map_err(|_| Error::WorkerDied)? }
+    }
+
+    /// Offer several representations of the same value on the clipboard at
+    /// once, as `(mime, data)` pairs.
+    pub fn write_mime(
+        &mut self,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        drop(self.store(Selection::Clipboard, from_pairs(contents), None)?);
+
+        Ok(())
+    }
+
+    /// Offer several representations of the same value on the primary
+    /// selection at once, as `(mime, data)` pairs.
+    pub fn write_primary_mime(
+        &mut self,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        drop(self.store(Selection::Primary, from_pairs(contents), None)?);
+
+        Ok(())
+    }
+
+    /// Offer the clipboard value as each of the given mime types, without
+    /// producing it up front.
+    ///
+    /// `provider` is called on the worker thread with the requested mime
+    /// type every time another client pastes. Returning an error sends
+    /// nothing.
+    pub fn write_lazy<F>(
+        &mut self,
+        mimes: &[&str],
+        provider: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Result<Vec<u8>, ProviderError> + Send + Sync + 'static,
+    {
+        let contents = lazy(mimes, Arc::new(provider));
+        drop(self.store(Selection::Clipboard, contents, None)?);
+
+        Ok(())
+    }
+
+    /// Offer the primary selection value as each of the given mime types,
+    /// without producing it up front.
+    ///
+    /// See [`Clipboard::write_lazy`].
+    pub fn write_primary_lazy<F>(
+        &mut self,
+        mimes: &[&str],
+        provider: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Result<Vec<u8>, ProviderError> + Send + Sync + 'static,
+    {
+        let contents = lazy(mimes, Arc::new(provider));
+        drop(self.store(Selection::Primary, contents, None)?);
+
+        Ok(())
+    }
+
+    /// Like [`Clipboard::write_mime`], but returning a future.
+    ///
+    /// See [`Clipboard::write_async`].
+    pub fn write_mime_async(
+        &mut self,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let stored =
+            self.store(Selection::Clipboard, from_pairs(contents), None);
+
+        async move { stored?.await.map_err(|_| Error::WorkerDied)? }
+    }
+
+    fn read_text(
+        &self,
+        target: Selection,
+    ) -> impl Future<Output = Result<String, Error>> + Send + 'static {
+        let loaded = self.load(target, &TEXT_MIME_TYPES);
+
+        async move {
+            let (mime, data) =
+                loaded?.await.map_err(|_| Error::WorkerDied)??;
+
+            let text = String::from_utf8(data).map_err(Error::InvalidUtf8)?;
+
+            // `text/*` mime types use CRLF line endings as per RFC 2046.
+            if mime == "UTF8_STRING" {
+                Ok(text)
+            } else {
+                Ok(text.replace("\r\n", "\n").replace('\r', "\n"))
+            }
+        }
+    }
+
+    fn write_text(
+        &mut self,
+        target: Selection,
+        data: String,
+        serial: Option<u32>,
+    ) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let data = Data::Ready(Arc::from(data.into_bytes()));
+
+        let contents = TEXT_MIME_TYPES
+            .iter()
+            .map(|mime| (mime.to_string(), data.clone()))
+            .collect();
+
+        self.store(target, contents, serial)
+    }
+
+    /// Ask the worker to load the first of `mimes` offered on a selection,
+    /// returning the receiver the result will be sent through.
+    fn load(
+        &self,
+        target: Selection,
+        mimes: &[&str],
+    ) -> Result<oneshot::Receiver<Loaded>, Error> {
+        let (sender, receiver) = oneshot::channel();
+        let mimes = mimes.iter().map(|mime| mime.to_string()).collect();
+
+        self.commands
+            .send(Command::Load(target, mimes, sender))
+            .map_err(|_| Error::WorkerDied)?;
+
+        Ok(receiver)
+    }
+
+    fn formats(&self, target: Selection) -> Result<Vec<String>, Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.commands
+            .send(Command::Formats(target, sender))
+            .map_err(|_| Error::WorkerDied)?;
+
+        receiver.recv().map_err(|_| Error::WorkerDied)?
+    }
+
+    /// Ask the worker to offer `contents` on a selection, returning the
+    /// receiver the outcome will be sent through.
+    fn store(
+        &mut self,
+        target: Selection,
+        contents: Vec<(String, Data)>,
+        serial: Option<u32>,
+    ) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let (sender, receiver) = oneshot::channel();
+        let contents = with_text_aliases(contents);
+
+        self.commands
+            .send(Command::Store(target, contents, serial, sender))
+            .map_err(|_| Error::WorkerDied)?;
+
+        Ok(receiver)
+    }
+}
+
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Exit);
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Offers UTF-8 text under every text mime type clients may ask for.
+fn with_text_aliases(mut contents: Vec<(String, Data)>) -> Vec<(String, Data)> {
+    let text = contents
+        .iter()
+        .find(|(mime, _)| mime == TEXT_MIME_TYPES[0])
+        .map(|(_, data)| data.clone());
+
+    if let Some(text) = text {
+        for alias in &TEXT_MIME_TYPES[1..] {
+            if !contents.iter().any(|(mime, _)| mime == alias) {
+                contents.push((alias.to_string(), text.clone()));
+            }
+        }
+    }
+
+    contents
+}
+
+fn from_pairs(contents: Vec<(String, Vec<u8>)>) -> Vec<(String, Data)> {
+    contents
+        .into_iter()
+        .map(|(mime, data)| (mime, Data::Ready(Arc::from(data))))
+        .collect()
+}
+
+fn lazy(mimes: &[&str], provider: worker::Provider) -> Vec<(String, Data)> {
+    mimes
+        .iter()
+        .map(|&mime| {
+            (
+                mime.to_owned(),
+                Data::Lazy(mime.to_owned(), provider.clone()),
+            )
+        })
+        .collect()
+}
+
+/// Drive `future` to completion, parking the current thread while it is
+/// pending.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unparker(thread::Thread);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unparker(thread::current())));
+    let mut context = task::Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
 }
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+/// The settings of a [`Clipboard`](crate::Clipboard) connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// How long the owner of a selection has to send its whole value.
+    pub read_timeout: Duration,
+    /// The largest value that will be read, in bytes.
+    ///
+    /// Larger values fail with [`Error::TooLarge`](crate::Error::TooLarge).
+    pub max_transfer_size: Option<usize>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            read_timeout: Duration::from_secs(3),
+            max_transfer_size: None,
+        }
+    }
+}
@@ -0,0 +1,1007 @@
+use futures_channel::oneshot;
+use sctk::data_device_manager::data_device::{DataDevice, DataDeviceHandler};
+use sctk::data_device_manager::data_offer::{
+    DataOfferError, DataOfferHandler, DragOffer,
+};
+use sctk::data_device_manager::data_source::{
+    CopyPasteSource, DataSourceHandler,
+};
+use sctk::data_device_manager::{DataDeviceManagerState, ReadPipe, WritePipe};
+use sctk::primary_selection::device::{
+    PrimarySelectionDevice, PrimarySelectionDeviceHandler,
+};
+use sctk::primary_selection::selection::{
+    PrimarySelectionSource, PrimarySelectionSourceHandler,
+};
+use sctk::primary_selection::PrimarySelectionManagerState;
+use sctk::reexports::calloop::timer::{TimeoutAction, Timer};
+use sctk::reexports::calloop::{LoopHandle, PostAction};
+use sctk::reexports::client::globals::GlobalList;
+use sctk::reexports::client::protocol::wl_data_device::WlDataDevice;
+use sctk::reexports::client::protocol::wl_data_device_manager::DndAction;
+use sctk::reexports::client::protocol::wl_data_source::WlDataSource;
+use sctk::reexports::client::protocol::wl_keyboard::{self, WlKeyboard};
+use sctk::reexports::client::protocol::wl_pointer::WlPointer;
+use sctk::reexports::client::protocol::wl_seat::WlSeat;
+use sctk::reexports::client::protocol::wl_surface::WlSurface;
+use sctk::reexports::client::{Connection, Dispatch, Proxy, QueueHandle};
+use sctk::reexports::protocols::wp::primary_selection::zv1::client::{
+    zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+    zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+};
+use sctk::registry::{ProvidesRegistryState, RegistryState};
+use sctk::seat::pointer::{
+    PointerData, PointerEvent, PointerEventKind, PointerHandler,
+};
+use sctk::seat::{Capability, SeatHandler, SeatState};
+use sctk::{
+    delegate_data_device, delegate_pointer, delegate_primary_selection,
+    delegate_registry, delegate_seat, registry_handlers,
+};
+use wayland_backend::client::ObjectId;
+
+use crate::data_control::{self, Offer};
+use crate::worker::{Data, Loaded};
+use crate::{ClipboardEvent, Error, Selection, Settings, Subscriber};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::Rc;
+
+/// A [`Loaded`] reply that is still waiting for its data.
+type PendingLoad = Rc<RefCell<Option<(oneshot::Sender<Loaded>, String)>>>;
+
+pub struct State {
+    pub exit: bool,
+
+    data_device_manager_state: Option<DataDeviceManagerState>,
+    primary_selection_manager_state: Option<PrimarySelectionManagerState>,
+    /// Replaces both managers above when the compositor allows us to
+    /// control selections without keyboard focus.
+    data_control: Option<data_control::Manager>,
+    registry_state: RegistryState,
+    seat_state: SeatState,
+
+    seats: HashMap<ObjectId, SeatData>,
+    /// The latest seat which received an input event.
+    latest_seat: Option<ObjectId>,
+
+    loop_handle: LoopHandle<'static, Self>,
+    queue_handle: QueueHandle<Self>,
+
+    data_sources: Vec<CopyPasteSource>,
+    data_contents: HashMap<String, Data>,
+
+    primary_sources: Vec<PrimarySelectionSource>,
+    primary_contents: HashMap<String, Data>,
+
+    /// The data control sources we offered, along with their selection.
+    control_sources: Vec<(Selection, data_control::Source)>,
+
+    subscribers: Vec<Subscriber>,
+
+    settings: Settings,
+}
+
+impl State {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<Self>,
+        loop_handle: LoopHandle<'static, Self>,
+        settings: Settings,
+    ) -> Option<Self> {
+        let data_device_manager_state =
+            DataDeviceManagerState::bind(globals, queue_handle).ok();
+        let primary_selection_manager_state =
+            PrimarySelectionManagerState::bind(globals, queue_handle).ok();
+
+        let data_control = data_control::Manager::bind(globals, queue_handle);
+
+        if data_device_manager_state.is_none()
+            && primary_selection_manager_state.is_none()
+            && data_control.is_none()
+        {
+            return None;
+        }
+
+        let seat_state = SeatState::new(globals, queue_handle);
+
+        // `ObjectId` is mutable, but its hash does not depend on it.
+        #[allow(clippy::mutable_key_type)]
+        let seats = seat_state
+            .seats()
+            .map(|seat| {
+                let mut data = SeatData::default();
+                data.control_device = data_control
+                    .as_ref()
+                    .map(|manager| manager.get_device(&seat, queue_handle));
+
+                (seat.id(), data)
+            })
+            .collect();
+
+        Some(State {
+            exit: false,
+            data_device_manager_state,
+            primary_selection_manager_state,
+            data_control,
+            registry_state: RegistryState::new(globals),
+            seat_state,
+            seats,
+            latest_seat: None,
+            loop_handle,
+            queue_handle: queue_handle.clone(),
+            data_sources: Vec::new(),
+            data_contents: HashMap::new(),
+            primary_sources: Vec::new(),
+            primary_contents: HashMap::new(),
+            control_sources: Vec::new(),
+            subscribers: Vec::new(),
+            settings,
+        })
+    }
+
+    /// Returns `true` if the compositor supports data control, so that
+    /// selections are available without keyboard focus.
+    pub fn has_data_control(&self) -> bool {
+        self.data_control.is_some()
+    }
+
+    /// Offer `contents` on the given selection of the latest active seat.
+    ///
+    /// The selection is set with `serial`, or with the serial of the latest
+    /// input event if it is `None`. Nothing is offered unless one of our
+    /// surfaces has keyboard focus, or data control is available.
+    pub fn store_selection(
+        &mut self,
+        target: Selection,
+        contents: Vec<(String, Data)>,
+        serial: Option<u32>,
+    ) -> Result<(), Error> {
+        if self.data_control.is_some() {
+            return self.control_selection(target, contents);
+        }
+
+        let seat = self.active_seat()?;
+
+        if !seat.has_focus {
+            return Err(Error::NoFocus);
+        }
+
+        let serial = serial.unwrap_or(seat.latest_serial);
+        let mimes: Vec<String> =
+            contents.iter().map(|(mime, _)| mime.clone()).collect();
+        let contents = contents.into_iter().collect();
+
+        match target {
+            Selection::Clipboard => {
+                let manager = self
+                    .data_device_manager_state
+                    .as_ref()
+                    .ok_or(Error::Unsupported("data device"))?;
+                let device = seat
+                    .data_device
+                    .as_ref()
+                    .ok_or(Error::Unsupported("data device"))?;
+
+                let source =
+                    manager.create_copy_paste_source(&self.queue_handle, mimes);
+                source.set_selection(device, serial);
+
+                self.data_contents = contents;
+                self.data_sources.push(source);
+            }
+            Selection::Primary => {
+                let manager = self
+                    .primary_selection_manager_state
+                    .as_ref()
+                    .ok_or(Error::Unsupported("primary selection"))?;
+                let device = seat
+                    .primary_device
+                    .as_ref()
+                    .ok_or(Error::Unsupported("primary selection"))?;
+
+                let source =
+                    manager.create_selection_source(&self.queue_handle, mimes);
+                source.set_selection(device, serial);
+
+                self.primary_contents = contents;
+                self.primary_sources.push(source);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Offer `contents` on the given selection through data control, which
+    /// needs neither focus nor a serial.
+    fn control_selection(
+        &mut self,
+        target: Selection,
+        contents: Vec<(String, Data)>,
+    ) -> Result<(), Error> {
+        let manager = self
+            .data_control
+            .as_ref()
+            .ok_or(Error::Unsupported("data control"))?;
+
+        if target == Selection::Primary && !manager.supports_primary() {
+            return Err(Error::Unsupported("primary selection"));
+        }
+
+        let device = self
+            .active_seat()?
+            .control_device
+            .as_ref()
+            .ok_or(Error::NoSeat)?;
+
+        let mimes: Vec<String> =
+            contents.iter().map(|(mime, _)| mime.clone()).collect();
+
+        let source = manager.create_source(target, &mimes, &self.queue_handle);
+        device.set_selection(target, &source);
+
+        match target {
+            Selection::Clipboard => {
+                self.data_contents = contents.into_iter().collect();
+            }
+            Selection::Primary => {
+                self.primary_contents = contents.into_iter().collect();
+            }
+        }
+
+        self.control_sources.push((target, source));
+
+        Ok(())
+    }
+
+    /// Record the new `offer` of the `target` selection of `seat`, as
+    /// announced by its data control device.
+    pub fn control_offer(
+        &mut self,
+        seat: &ObjectId,
+        target: Selection,
+        offer: Option<Offer>,
+    ) {
+        let device = self
+            .seats
+            .get_mut(seat)
+            .and_then(|data| data.control_device.as_mut());
+
+        if let Some(device) = device {
+            device.set_offer(target, offer);
+            self.notify(ClipboardEvent::Changed(target));
+        }
+    }
+
+    /// Forget the data control source with the given `id`, once another
+    /// client replaced it.
+    pub fn control_cancelled(&mut self, target: Selection, id: &ObjectId) {
+        let is_current = self
+            .control_sources
+            .iter()
+            .rev()
+            .find(|(selection, _)| *selection == target)
+            .is_some_and(|(_, source)| source.is(id));
+
+        self.control_sources.retain(|(_, source)| !source.is(id));
+
+        if is_current {
+            self.notify(ClipboardEvent::OwnershipLost(target));
+        }
+    }
+
+    /// Drop the data control device of `seat`, which the compositor
+    /// invalidated.
+    pub fn remove_control_device(&mut self, seat: &ObjectId) {
+        if let Some(data) = self.seats.get_mut(seat) {
+            data.control_device = None;
+        }
+    }
+
+    /// The seat selections are exchanged through: the latest seat which
+    /// received an input event or, since data control does not need any
+    /// input, any seat.
+    fn active_seat(&self) -> Result<&SeatData, Error> {
+        self.latest_seat
+            .as_ref()
+            .and_then(|seat| self.seats.get(seat))
+            .or_else(|| {
+                self.data_control
+                    .as_ref()
+                    .and_then(|_| self.seats.values().next())
+            })
+            .ok_or(Error::NoSeat)
+    }
+
+    pub fn subscribe(&mut self, subscriber: Subscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn notify(&mut self, event: ClipboardEvent) {
+        self.subscribers.retain_mut(|subscriber| subscriber(event));
+    }
+
+    /// List the mime types offered on the given selection.
+    pub fn available_formats(
+        &self,
+        target: Selection,
+    ) -> Result<Vec<String>, Error> {
+        let seat = self.active_seat()?;
+
+        if let Some(device) = &seat.control_device {
+            return Ok(device
+                .offer(target)
+                .map(Offer::mime_types)
+                .unwrap_or_default());
+        }
+
+        let mimes = match target {
+            Selection::Clipboard => seat
+                .data_device
+                .as_ref()
+                .and_then(|device| device.data().selection_offer())
+                .map(|offer| offer.with_mime_types(<[String]>::to_vec)),
+            Selection::Primary => seat
+                .primary_device
+                .as_ref()
+                .and_then(|device| device.data().selection_offer())
+                .map(|offer| offer.with_mime_types(<[String]>::to_vec)),
+        };
+
+        Ok(mimes.unwrap_or_default())
+    }
+
+    /// Start receiving the first of `mimes` offered on the given selection.
+    ///
+    /// The result is sent through `reply` once the transfer finishes. The
+    /// transfer is abandoned if `reply` is cancelled.
+    pub fn load_selection(
+        &mut self,
+        target: Selection,
+        mimes: Vec<String>,
+        reply: oneshot::Sender<Loaded>,
+    ) {
+        match self.open_selection(target, &mimes) {
+            Ok((pipe, mime)) => self.receive(pipe, mime, reply),
+            Err(error) => {
+                let _ = reply.send(Err(error));
+            }
+        }
+    }
+
+    /// Ask the owner of the given selection to send the first of `mimes` it
+    /// offers, returning the pipe it is sent through.
+    fn open_selection(
+        &self,
+        target: Selection,
+        mimes: &[String],
+    ) -> Result<(ReadPipe, String), Error> {
+        let seat = self.active_seat()?;
+
+        let choose = |offered: &[String]| {
+            mimes.iter().find(|mime| offered.contains(mime)).cloned()
+        };
+
+        if let Some(device) = &seat.control_device {
+            let offer = device.offer(target).ok_or(Error::Empty)?;
+            let mime = choose(&offer.mime_types())
+                .ok_or_else(|| Error::NoMimeType(mimes.to_vec()))?;
+
+            return Ok((offer.receive(mime.clone())?, mime));
+        }
+
+        if !seat.has_focus {
+            return Err(Error::NoFocus);
+        }
+
+        let (pipe, mime) = match target {
+            Selection::Clipboard => {
+                let offer = seat
+                    .data_device
+                    .as_ref()
+                    .and_then(|device| device.data().selection_offer())
+                    .ok_or(Error::Empty)?;
+
+                let mime = offer
+                    .with_mime_types(choose)
+                    .ok_or_else(|| Error::NoMimeType(mimes.to_vec()))?;
+
+                let pipe =
+                    offer.receive(mime.clone()).map_err(
+                        |error| match error {
+                            DataOfferError::InvalidReceive => Error::NotReady,
+                            DataOfferError::Io(error) => Error::Io(error),
+                        },
+                    )?;
+
+                (pipe, mime)
+            }
+            Selection::Primary => {
+                let offer = seat
+                    .primary_device
+                    .as_ref()
+                    .and_then(|device| device.data().selection_offer())
+                    .ok_or(Error::Empty)?;
+
+                let mime = offer
+                    .with_mime_types(choose)
+                    .ok_or_else(|| Error::NoMimeType(mimes.to_vec()))?;
+
+                (offer.receive(mime.clone())?, mime)
+            }
+        };
+
+        Ok((pipe, mime))
+    }
+
+    fn receive(
+        &mut self,
+        pipe: ReadPipe,
+        mime: String,
+        reply: oneshot::Sender<Loaded>,
+    ) {
+        if let Err(error) = set_non_blocking(pipe.as_raw_fd()) {
+            let _ = reply.send(Err(Error::Io(error)));
+            return;
+        }
+
+        let mut buffer = [0; 4096];
+        let mut contents = Vec::new();
+        let max_transfer_size = self.settings.max_transfer_size;
+        let pending: PendingLoad = Rc::new(RefCell::new(Some((reply, mime))));
+
+        let receiving = Rc::clone(&pending);
+        let inserted =
+            self.loop_handle.insert_source(pipe, move |_, file, _| {
+                let mut reply = receiving.borrow_mut();
+
+                // Nobody is waiting for the data anymore, so dropping the pipe
+                // ends the transfer early.
+                if reply.as_ref().is_none_or(|(reply, _)| reply.is_canceled()) {
+                    *reply = None;
+                    return PostAction::Remove;
+                }
+
+                // Safety: the pipe is not dropped while we read from it.
+                let file = unsafe { file.get_mut() };
+
+                loop {
+                    match file.read(&mut buffer) {
+                        Ok(0) => {
+                            let contents = std::mem::take(&mut contents);
+
+                            if let Some((reply, mime)) = reply.take() {
+                                let _ = reply.send(Ok((mime, contents)));
+                            }
+
+                            break PostAction::Remove;
+                        }
+                        Ok(n) => {
+                            contents.extend_from_slice(&buffer[..n]);
+
+                            if let Some(max) = max_transfer_size
+                                .filter(|&max| contents.len() > max)
+                            {
+                                if let Some((reply, _)) = reply.take() {
+                                    let _ =
+                                        reply.send(Err(Error::TooLarge(max)));
+                                }
+
+                                break PostAction::Remove;
+                            }
+                        }
+                        Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                            break PostAction::Continue;
+                        }
+                        Err(error) => {
+                            if let Some((reply, _)) = reply.take() {
+                                let _ = reply.send(Err(Error::Io(error)));
+                            }
+
+                            break PostAction::Remove;
+                        }
+                    }
+                }
+            });
+
+        let token = match inserted {
+            Ok(token) => token,
+            Err(error) => {
+                if let Some((reply, _)) = pending.borrow_mut().take() {
+                    let _ = reply.send(Err(Error::Io(error.error.into())));
+                }
+
+                return;
+            }
+        };
+
+        // Give up on owners that take too long to send their value. The timer
+        // does nothing if the transfer is over by the time it fires.
+        let timer = Timer::from_duration(self.settings.read_timeout);
+
+        let _ = self.loop_handle.insert_source(timer, move |_, _, state| {
+            if let Some((reply, _)) = pending.borrow_mut().take() {
+                let _ = reply.send(Err(Error::Timeout));
+                state.loop_handle.remove(token);
+            }
+
+            TimeoutAction::Drop
+        });
+    }
+
+    pub fn send(&mut self, target: Selection, pipe: WritePipe, mime: String) {
+        // Look the data up now, the selection may change during the send.
+        let contents = match target {
+            Selection::Clipboard => self.data_contents.get(&mime),
+            Selection::Primary => self.primary_contents.get(&mime),
+        };
+
+        match contents.cloned() {
+            Some(Data::Ready(contents)) => self.write_to(pipe, contents),
+            // The provider runs on the worker thread, so other events wait
+            // until it returns.
+            Some(Data::Lazy(mime, provider)) => {
+                if let Ok(contents) = provider(&mime) {
+                    self.write_to(pipe, contents);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn write_to(
+        &mut self,
+        pipe: WritePipe,
+        contents: impl AsRef<[u8]> + 'static,
+    ) {
+        if set_non_blocking(pipe.as_raw_fd()).is_err() {
+            return;
+        }
+
+        let mut written = 0;
+
+        let _ = self.loop_handle.insert_source(pipe, move |_, file, _| {
+            // Safety: the pipe is not dropped while we write to it.
+            let file = unsafe { file.get_mut() };
+
+            let contents = contents.as_ref();
+
+            loop {
+                if written == contents.len() {
+                    break PostAction::Remove;
+                }
+
+                match file.write(&contents[written..]) {
+                    Ok(n) => written += n,
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                        break PostAction::Continue;
+                    }
+                    Err(_) => break PostAction::Remove,
+                }
+            }
+        });
+    }
+}
+
+impl SeatHandler for State {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(
+        &mut self,
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+        seat: WlSeat,
+    ) {
+        let mut data = SeatData::default();
+        data.control_device = self
+            .data_control
+            .as_ref()
+            .map(|manager| manager.get_device(&seat, queue_handle));
+
+        let _ = self.seats.insert(seat.id(), data);
+    }
+
+    fn new_capability(
+        &mut self,
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+        seat: WlSeat,
+        capability: Capability,
+    ) {
+        let data = match self.seats.get_mut(&seat.id()) {
+            Some(data) => data,
+            None => return,
+        };
+
+        match capability {
+            Capability::Keyboard => {
+                data.keyboard =
+                    Some(seat.get_keyboard(queue_handle, seat.id()));
+
+                // Selections are tied to the keyboard, so devices are
+                // created and dropped along with it. Data control devices
+                // are used instead when available.
+                if self.data_control.is_some() {
+                    return;
+                }
+
+                if data.data_device.is_none() {
+                    data.data_device = self
+                        .data_device_manager_state
+                        .as_ref()
+                        .map(|manager| {
+                            manager.get_data_device(queue_handle, &seat)
+                        });
+                }
+
+                if data.primary_device.is_none() {
+                    data.primary_device = self
+                        .primary_selection_manager_state
+                        .as_ref()
+                        .map(|manager| {
+                            manager.get_selection_device(queue_handle, &seat)
+                        });
+                }
+            }
+            Capability::Pointer => {
+                data.pointer =
+                    self.seat_state.get_pointer(queue_handle, &seat).ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        seat: WlSeat,
+        capability: Capability,
+    ) {
+        let data = match self.seats.get_mut(&seat.id()) {
+            Some(data) => data,
+            None => return,
+        };
+
+        match capability {
+            Capability::Keyboard => {
+                data.data_device = None;
+                data.primary_device = None;
+
+                if let Some(keyboard) = data.keyboard.take() {
+                    if keyboard.version() >= 3 {
+                        keyboard.release();
+                    }
+                }
+            }
+            Capability::Pointer => {
+                if let Some(pointer) = data.pointer.take() {
+                    if pointer.version() >= 3 {
+                        pointer.release();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn remove_seat(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        seat: WlSeat,
+    ) {
+        let _ = self.seats.remove(&seat.id());
+    }
+}
+
+impl PointerHandler for State {
+    fn pointer_frame(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        pointer: &WlPointer,
+        events: &[PointerEvent],
+    ) {
+        let seat = match pointer.data::<PointerData>() {
+            Some(data) => data.seat().id(),
+            None => return,
+        };
+
+        let data = match self.seats.get_mut(&seat) {
+            Some(data) => data,
+            None => return,
+        };
+
+        let mut serial = None;
+
+        for event in events {
+            match event.kind {
+                PointerEventKind::Press { serial: new, .. }
+                | PointerEventKind::Release { serial: new, .. } => {
+                    serial = Some(new);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(serial) = serial {
+            data.latest_serial = serial;
+            self.latest_seat = Some(seat);
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, ObjectId, State> for State {
+    fn event(
+        state: &mut State,
+        _: &WlKeyboard,
+        event: wl_keyboard::Event,
+        seat: &ObjectId,
+        _: &Connection,
+        _: &QueueHandle<State>,
+    ) {
+        let data = match state.seats.get_mut(seat) {
+            Some(data) => data,
+            None => return,
+        };
+
+        match event {
+            wl_keyboard::Event::Key { serial, .. }
+            | wl_keyboard::Event::Modifiers { serial, .. } => {
+                data.latest_serial = serial;
+                state.latest_seat = Some(seat.clone());
+            }
+            // Both selections rely on keyboard focus.
+            wl_keyboard::Event::Enter { serial, .. } => {
+                data.latest_serial = serial;
+                data.has_focus = true;
+                state.latest_seat = Some(seat.clone());
+            }
+            wl_keyboard::Event::Leave { .. } => {
+                data.latest_serial = 0;
+                data.has_focus = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl DataDeviceHandler for State {
+    fn enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+        _: f64,
+        _: f64,
+        _: &WlSurface,
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+    ) {
+    }
+
+    fn motion(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+        _: f64,
+        _: f64,
+    ) {
+    }
+
+    fn selection(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+    ) {
+        self.notify(ClipboardEvent::Changed(Selection::Clipboard));
+    }
+
+    fn drop_performed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+    ) {
+    }
+}
+
+impl DataOfferHandler for State {
+    fn source_actions(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &mut DragOffer,
+        _: DndAction,
+    ) {
+    }
+
+    fn selected_action(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &mut DragOffer,
+        _: DndAction,
+    ) {
+    }
+}
+
+impl DataSourceHandler for State {
+    fn accept_mime(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        _: Option<String>,
+    ) {
+    }
+
+    fn send_request(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        mime: String,
+        pipe: WritePipe,
+    ) {
+        self.send(Selection::Clipboard, pipe, mime);
+    }
+
+    fn cancelled(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &WlDataSource,
+    ) {
+        // Replacing our own selection cancels the previous source as well.
+        let is_current =
+            self.data_sources.last().map(|data| data.inner()) == Some(source);
+
+        self.data_sources.retain(|data| data.inner() != source);
+
+        if is_current {
+            self.notify(ClipboardEvent::OwnershipLost(Selection::Clipboard));
+        }
+    }
+
+    fn dnd_dropped(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+    ) {
+    }
+
+    fn dnd_finished(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+    ) {
+    }
+
+    fn action(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        _: DndAction,
+    ) {
+    }
+}
+
+impl PrimarySelectionDeviceHandler for State {
+    fn selection(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &ZwpPrimarySelectionDeviceV1,
+    ) {
+        self.notify(ClipboardEvent::Changed(Selection::Primary));
+    }
+}
+
+impl PrimarySelectionSourceHandler for State {
+    fn send_request(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &ZwpPrimarySelectionSourceV1,
+        mime: String,
+        pipe: WritePipe,
+    ) {
+        self.send(Selection::Primary, pipe, mime);
+    }
+
+    fn cancelled(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &ZwpPrimarySelectionSourceV1,
+    ) {
+        // Replacing our own selection cancels the previous source as well.
+        let is_current = self.primary_sources.last().map(|data| data.inner())
+            == Some(source);
+
+        self.primary_sources.retain(|data| data.inner() != source);
+
+        if is_current {
+            self.notify(ClipboardEvent::OwnershipLost(Selection::Primary));
+        }
+    }
+}
+
+impl ProvidesRegistryState for State {
+    registry_handlers![SeatState];
+
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+}
+
+delegate_seat!(State);
+delegate_pointer!(State);
+delegate_data_device!(State);
+delegate_primary_selection!(State);
+delegate_registry!(State);
+
+#[derive(Debug, Default)]
+struct SeatData {
+    keyboard: Option<WlKeyboard>,
+    pointer: Option<WlPointer>,
+    data_device: Option<DataDevice>,
+    primary_device: Option<PrimarySelectionDevice>,
+    control_device: Option<data_control::Device>,
+    has_focus: bool,
+    /// The latest input serial, used to set selections.
+    latest_serial: u32,
+}
+
+impl Drop for SeatData {
+    fn drop(&mut self) {
+        if let Some(keyboard) = self.keyboard.take() {
+            if keyboard.version() >= 3 {
+                keyboard.release();
+            }
+        }
+
+        if let Some(pointer) = self.pointer.take() {
+            if pointer.version() >= 3 {
+                pointer.release();
+            }
+        }
+    }
+}
+
+fn set_non_blocking(fd: RawFd) -> io::Result<()> {
+    // Safety: `fd` is a valid file descriptor owned by a pipe.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Safety: see above.
+    let result =
+        unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::state::State;
+use crate::{Error, ProviderError, Selection, Settings, Subscriber};
+
+use futures_channel::oneshot;
+use sctk::reexports::calloop::channel::{self, Channel};
+use sctk::reexports::calloop::EventLoop;
+use sctk::reexports::calloop_wayland_source::WaylandSource;
+use sctk::reexports::client::globals::registry_queue_init;
+use sctk::reexports::client::Connection;
+
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+
+/// The mime type chosen by a [`Command::Load`] along with its data.
+pub type Loaded = Result<(String, Vec<u8>), Error>;
+
+/// Produces the data of a mime type when another client asks for it.
+pub type Provider =
+    Arc<dyn Fn(&str) -> Result<Vec<u8>, ProviderError> + Send + Sync>;
+
+/// The value offered as a mime type.
+#[derive(Clone)]
+pub enum Data {
+    /// The value, known up front.
+    Ready(Arc<[u8]>),
+    /// The value is produced by calling the [`Provider`] with the given mime
+    /// type.
+    Lazy(String, Provider),
+}
+
+/// A request sent to the clipboard worker.
+pub enum Command {
+    /// Offer the given `(mime, data)` pairs on a selection, with the serial
+    /// of the input event that caused it, if known.
+    Store(
+        Selection,
+        Vec<(String, Data)>,
+        Option<u32>,
+        oneshot::Sender<Result<(), Error>>,
+    ),
+    /// Load the first available mime type, in order of preference.
+    Load(Selection, Vec<String>, oneshot::Sender<Loaded>),
+    /// List the mime types offered on a selection.
+    Formats(Selection, Sender<Result<Vec<String>, Error>>),
+    /// Notify a subscriber of every selection change.
+    Subscribe(Subscriber),
+    /// Shut the worker down.
+    Exit,
+}
+
+/// Spawn a worker that dispatches its own event queue and serves
+/// clipboard requests.
+///
+/// Once it is ready, the worker sends through `started` whether the
+/// compositor supports data control. `started` is dropped instead if the
+/// worker fails to start.
+pub fn spawn(
+    connection: Connection,
+    commands: Channel<Command>,
+    settings: Settings,
+    started: oneshot::Sender<bool>,
+) -> Option<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("clipboard_wayland"))
+        .spawn(move || run(connection, commands, settings, started))
+        .ok()
+}
+
+fn run(
+    connection: Connection,
+    commands: Channel<Command>,
+    settings: Settings,
+    started: oneshot::Sender<bool>,
+) {
+    let (globals, event_queue) = match registry_queue_init(&connection) {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+
+    let mut event_loop = match EventLoop::<State>::try_new() {
+        Ok(event_loop) => event_loop,
+        Err(_) => return,
+    };
+    let loop_handle = event_loop.handle();
+
+    let mut state = match State::new(
+        &globals,
+        &event_queue.handle(),
+        loop_handle.clone(),
+        settings,
+    ) {
+        Some(state) => state,
+        None => return,
+    };
+
+    let inserted = loop_handle.insert_source(commands, |event, _, state| {
+        if let channel::Event::Msg(command) = event {
+            match command {
+                Command::Store(target, contents, serial, reply) => {
+                    let _ = reply
+                        .send(state.store_selection(target, contents, serial));
+                }
+                Command::Load(target, mimes, reply) => {
+                    state.load_selection(target, mimes, reply);
+                }
+                Command::Formats(target, reply) => {
+                    let _ = reply.send(state.available_formats(target));
+                }
+                Command::Subscribe(subscriber) => {
+                    state.subscribe(subscriber);
+                }
+                Command::Exit => state.exit = true,
+            }
+        }
+    });
+
+    if inserted.is_err()
+        || WaylandSource::new(connection, event_queue)
+            .insert(loop_handle)
+            .is_err()
+    {
+        return;
+    }
+
+    let _ = started.send(state.has_data_control());
+
+    while !state.exit {
+        if event_loop.dispatch(None, &mut state).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use clipboard_testing::WaylandCompositor;
+use clipboard_wayland::Clipboard;
+
+#[test]
+#[ignore = "needs Weston or Sway"]
+fn connects_to_a_headless_compositor() {
+    let compositor = WaylandCompositor::start()
+        .unwrap()
+        .expect("Weston or Sway is needed to run this test");
+
+    // This is the only test of this file, so nothing else reads the
+    // environment meanwhile.
+    for (name, value) in compositor.env().iter() {
+        std::env::set_var(name, value);
+    }
+
+    let mut clipboard = Clipboard::connect_to_env().unwrap();
+
+    // Without a window, the selection is only available through data
+    // control.
+    match clipboard.has_data_control() {
+        Some(true) => {
+            clipboard.write("Hello".to_owned()).unwrap();
+
+            assert_eq!(clipboard.read().unwrap(), "Hello");
+        }
+        Some(false) => {}
+        None => panic!("connecting to the environment reports data control"),
+    }
+}
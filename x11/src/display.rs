@@ -0,0 +1,86 @@
+//! Finding the display the connection of an application talks to, so that
+//! the clipboard can connect to the same one.
+use rustix::fd::{AsFd, BorrowedFd};
+use rustix::net::{self, AddressFamily, SocketAddrUnix};
+use x11rb::xcb_ffi::XCBConnection;
+
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+/// The TCP port of the display `:0`. The port of any other display is
+/// offset by its number.
+const X_TCP_PORT: u16 = 6000;
+
+/// The prefix of the path of the Unix socket of a local display, followed
+/// by its number.
+const X_UNIX_PATH: &[u8] = b"/tmp/.X11-unix/X";
+
+/// The name of the display an Xlib `Display` is connected to, along with
+/// the given `screen`.
+///
+/// Returns `None` if the display cannot be told from its socket.
+///
+/// # Safety
+///
+/// `display` must point to a valid Xlib `Display`.
+pub unsafe fn xlib_display_name(
+    display: NonNull<c_void>,
+    screen: c_int,
+) -> Option<String> {
+    // The public start of the Xlib `Display`, as read by its
+    // `ConnectionNumber` macro.
+    #[repr(C)]
+    struct Display {
+        ext_data: *mut c_void,
+        private1: *mut c_void,
+        fd: c_int,
+    }
+
+    let fd = display.cast::<Display>().as_ref().fd;
+
+    socket_display_name(BorrowedFd::borrow_raw(fd), screen)
+}
+
+/// The name of the display an `xcb_connection_t` is connected to, along
+/// with the given `screen`.
+///
+/// Returns `None` if the display cannot be told from its socket.
+///
+/// # Safety
+///
+/// `connection` must point to a valid `xcb_connection_t`.
+pub unsafe fn xcb_display_name(
+    connection: NonNull<c_void>,
+    screen: c_int,
+) -> Option<String> {
+    let connection =
+        XCBConnection::from_raw_xcb_connection(connection.as_ptr(), false)
+            .ok()?;
+
+    socket_display_name(connection.as_fd(), screen)
+}
+
+/// Recover the name of a display from the address of its socket.
+fn socket_display_name(fd: BorrowedFd<'_>, screen: c_int) -> Option<String> {
+    let address = net::getpeername(fd).ok()??;
+
+    let display = if address.address_family() == AddressFamily::UNIX {
+        let address = SocketAddrUnix::try_from(address).ok()?;
+        let path = address.path_bytes().or_else(|| address.abstract_name())?;
+        let number = std::str::from_utf8(path.strip_prefix(X_UNIX_PATH)?)
+            .ok()?
+            .parse::<u16>()
+            .ok()?;
+
+        format!(":{}", number)
+    } else {
+        let address = std::net::SocketAddr::try_from(address).ok()?;
+        let number = address.port().checked_sub(X_TCP_PORT)?;
+
+        format!("{}:{}", address.ip(), number)
+    };
+
+    Some(format!("{}.{}", display, screen))
+}
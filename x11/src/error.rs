@@ -14,14 +14,34 @@ pub enum Error {
     ReplyError(#[from] ReplyError),
     #[error("timeout")]
     Timeout,
+    #[error("transfer exceeds the maximum size of {0} bytes")]
+    TooLarge(usize),
     #[error("unexpected type: {0}")]
     UnexpectedType(Atom),
     #[error("invalid utf8 string: {0}")]
     InvalidUtf8(std::string::FromUtf8Error),
+    #[error("invalid {0} text")]
+    InvalidText(&'static str),
     #[error("deadlock")]
     SelectionLocked,
+    #[error("selection is empty")]
+    Empty,
+    #[error("no conversion to the requested targets")]
+    NoConversion,
+    #[error("no clipboard manager is running")]
+    NoClipboardManager,
     #[error("invalid selection owner")]
     InvalidOwner,
+    #[error("missing extension: {0}")]
+    MissingExtension(&'static str),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("a background thread panicked")]
+    ThreadPanicked,
+    #[error("worker thread disconnected")]
+    WorkerDisconnected,
+    #[error("reader thread disconnected")]
+    ReaderDisconnected,
     #[error("worker communication error")]
     SendError(#[from] mpsc::SendError<Atom>),
 }
@@ -0,0 +1,27 @@
+/// An X11 selection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The `CLIPBOARD` selection, used by explicit copy and paste.
+    Clipboard,
+    /// The `PRIMARY` selection, holding the latest selected text.
+    Primary,
+    /// The `SECONDARY` selection, used by some editors to swap text with
+    /// the `PRIMARY` selection.
+    Secondary,
+    /// Any other selection, by the name of its atom.
+    Named(String),
+}
+
+/// A change of the contents or ownership of a [`Selection`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardEvent {
+    /// The selection has a new owner, which may be ourselves.
+    Changed(Selection),
+    /// Another client took ownership of a selection we owned.
+    OwnershipLost(Selection),
+}
+
+/// A callback notified of every [`ClipboardEvent`].
+///
+/// It is unsubscribed as soon as it returns `false`.
+pub type Subscriber = Box<dyn FnMut(ClipboardEvent) -> bool + Send>;
@@ -1,276 +1,816 @@
+mod display;
 #[forbid(unsafe_code)]
 mod error;
+mod event;
+mod reader;
+mod settings;
+mod text;
 
+pub use display::{xcb_display_name, xlib_display_name};
 pub use error::Error;
+pub use event::{ClipboardEvent, Selection, Subscriber};
+pub use settings::Settings;
+pub use x11rb::protocol::xproto::Timestamp;
 
-use x11rb::connection::Connection as _;
+use reader::{Conversion, Reader, Request};
+
+use futures_channel::oneshot;
+use x11rb::connection::{Connection as _, RequestConnection as _};
 use x11rb::errors::ConnectError;
-use x11rb::protocol::xproto::{self, Atom, AtomEnum, EventMask, Window};
+use x11rb::protocol::xfixes::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    self, Atom, AtomEnum, EventMask, PropertyNotifyEvent,
+    SelectionRequestEvent, Window,
+};
 use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection as Connection;
 use x11rb::wrapper::ConnectionExt;
 
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::future::Future;
+use std::mem;
+use std::pin::pin;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::task::{self, Poll, Wake, Waker};
 use std::thread;
-use std::time::{Duration, Instant};
 
-const POLL_DURATION: std::time::Duration = Duration::from_micros(50);
+/// The selections we own.
+type Selections = HashMap<Atom, Owned>;
+
+/// The callers waiting for the worker to notice a server time, or `None`
+/// once the worker has stopped.
+type Timestamps = Arc<Mutex<Option<Vec<oneshot::Sender<Timestamp>>>>>;
+
+/// A selection we own.
+#[derive(Clone)]
+struct Owned {
+    /// The server time we acquired the selection at.
+    time: Timestamp,
+    /// The `(target, value)` pairs we serve.
+    contents: Vec<(Atom, Data)>,
+}
+
+/// Produces the data of a mime type when another client asks for it.
+type Provider =
+    Arc<dyn Fn(&str) -> Result<Vec<u8>, ProviderError> + Send + Sync>;
+
+/// The error a lazy data provider fails with.
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The value of a target we own.
+#[derive(Clone)]
+enum Data {
+    /// The value, known up front.
+    Ready(Arc<[u8]>),
+    /// The value is produced by calling the [`Provider`] with the given mime
+    /// type.
+    Lazy(String, Provider),
+    /// Text, encoded for the requested target when asked for.
+    Text(Arc<str>),
+}
+
+/// The mime types that are also served as `UTF8_STRING`.
+const TEXT_MIME_TYPES: [&str; 2] = ["text/plain;charset=utf-8", "text/plain"];
 
 /// A connection to an X11 [`Clipboard`].
 pub struct Clipboard {
-    reader: Context,
+    /// Dropped on shutdown, which stops the reader thread.
+    reader: Option<mpsc::Sender<Request>>,
     writer: Arc<Context>,
-    selections: Arc<RwLock<HashMap<Atom, (Atom, Vec<u8>)>>>,
+    selections: Arc<RwLock<Selections>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    has_xfixes: bool,
+    /// The callers waiting for the worker to notice a server time.
+    timestamps: Timestamps,
+    /// The failures of the worker, waiting to be taken.
+    errors: Arc<Mutex<Vec<Error>>>,
+    /// The worker and reader threads, joined on shutdown.
+    threads: Vec<thread::JoinHandle<()>>,
 }
 
 impl Clipboard {
     /// Connect to the running X11 server and obtain a [`Clipboard`].
     pub fn connect() -> Result<Self, Error> {
-        let reader = Context::new(None)?;
-        let writer = Arc::new(Context::new(None)?);
+        Self::connect_with(Settings::default())
+    }
+
+    /// Connect to the running X11 server and obtain a [`Clipboard`] with the
+    /// given [`Settings`].
+    pub fn connect_with(settings: Settings) -> Result<Self, Error> {
+        Self::open(None, settings)
+    }
+
+    /// Connect to the X11 server of the given `display`, like `":1"`, and
+    /// obtain a [`Clipboard`].
+    pub fn connect_to(display: &str) -> Result<Self, Error> {
+        Self::connect_to_with(display, Settings::default())
+    }
+
+    /// Connect to the X11 server of the given `display` and obtain a
+    /// [`Clipboard`] with the given [`Settings`].
+    pub fn connect_to_with(
+        display: &str,
+        settings: Settings,
+    ) -> Result<Self, Error> {
+        Self::open(Some(display), settings)
+    }
+
+    /// Connect to `display`, or to the one named by `$DISPLAY` if `None`.
+    fn open(display: Option<&str>, settings: Settings) -> Result<Self, Error> {
+        let (reader, reading) =
+            Reader::spawn(Context::new(display)?, settings)?;
+        let writer = Arc::new(Context::new(display)?);
         let selections = Arc::new(RwLock::new(HashMap::new()));
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let has_xfixes = writer.select_selection_changes()?;
+        let timestamps = Arc::new(Mutex::new(Some(Vec::new())));
+        let errors = Arc::new(Mutex::new(Vec::new()));
 
         let worker = Worker {
             context: Arc::clone(&writer),
             selections: Arc::clone(&selections),
+            subscribers: Arc::clone(&subscribers),
+            transfers: HashMap::new(),
+            incr_chunk_size: settings.incr_chunk_size,
+            timestamps: Arc::clone(&timestamps),
+            errors: Arc::clone(&errors),
         };
 
-        thread::spawn(move || worker.run());
+        let working = thread::spawn(move || worker.run());
 
         Ok(Clipboard {
-            reader,
+            reader: Some(reader),
             writer,
             selections,
+            subscribers,
+            has_xfixes,
+            timestamps,
+            errors,
+            threads: vec![working, reading],
         })
     }
 
-    fn read_selection(&self, selection: Atom) -> Result<String, Error> {
-        Ok(String::from_utf8(self.load(
-            selection,
-            self.reader.atoms.utf8_string,
-            self.reader.atoms.property,
-            std::time::Duration::from_secs(3),
-        )?)
-        .map_err(Error::InvalidUtf8)?)
+    /// Take the errors the background worker ran into while serving other
+    /// clients, since the last call.
+    ///
+    /// The worker keeps serving after most errors, so these are only worth
+    /// logging. Only the latest [`Worker::MAX_ERRORS`] errors are kept.
+    pub fn take_errors(&self) -> Vec<Error> {
+        self.errors
+            .lock()
+            .map(|mut errors| errors.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    /// Relinquish the selections we own, destroy our windows, and wait for
+    /// the background threads to stop.
+    ///
+    /// This is also done when the [`Clipboard`] is dropped, but errors can
+    /// only be observed here.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        if self.threads.is_empty() {
+            return Ok(());
+        }
+
+        // The worker stops once it is notified that its window is gone, so
+        // it is destroyed even if relinquishing the selections failed.
+        let released = self.release();
+        let destroyed =
+            xproto::destroy_window(&self.writer.connection, self.writer.window)
+                .map_err(Error::from)
+                .and_then(|_| Ok(self.writer.connection.flush()?));
+
+        // The reader stops once its channel is closed.
+        drop(self.reader.take());
+
+        let mut joined = Ok(());
+
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                joined = Err(Error::ThreadPanicked);
+            }
+        }
+
+        released.and(destroyed).and(joined)
+    }
+
+    /// Give up the ownership of every selection we still own.
+    ///
+    /// Each selection is relinquished as of the time we acquired it, so
+    /// that the server ignores us if another client took it meanwhile.
+    fn release(&self) -> Result<(), Error> {
+        let selections: Vec<(Atom, Timestamp)> = self
+            .selections
+            .write()
+            .map_err(|_| Error::SelectionLocked)?
+            .drain()
+            .map(|(selection, owned)| (selection, owned.time))
+            .collect();
+
+        for (selection, time) in selections {
+            let owner = xproto::get_selection_owner(
+                &self.writer.connection,
+                selection,
+            )?
+            .reply()?
+            .owner;
+
+            if owner == self.writer.window {
+                let _ = xproto::set_selection_owner(
+                    &self.writer.connection,
+                    x11rb::NONE,
+                    selection,
+                    time,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subscribe to changes of the CLIPBOARD and PRIMARY selections, and to
+    /// the loss of any selection we own.
+    ///
+    /// The `subscriber` is called from a background thread until it returns
+    /// `false`. Changes made by other clients are only noticed when the
+    /// XFixes extension is available.
+    pub fn subscribe(
+        &self,
+        subscriber: impl FnMut(ClipboardEvent) -> bool + Send + 'static,
+    ) -> Result<(), Error> {
+        if !self.has_xfixes {
+            return Err(Error::MissingExtension(xfixes::X11_EXTENSION_NAME));
+        }
+
+        self.subscribers
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?
+            .push(Box::new(subscriber));
+
+        Ok(())
+    }
+
+    /// Ask the reader thread to convert `selection` to the first of
+    /// `targets` its owner supports, at the given `time`.
+    ///
+    /// The conversion starts right away. Dropping the returned future
+    /// cancels it.
+    fn convert(
+        &self,
+        selection: Atom,
+        targets: Vec<Atom>,
+        time: Timestamp,
+    ) -> impl Future<Output = Conversion> + Send + 'static {
+        let (reply, conversion) = oneshot::channel();
+
+        let sent = self.reader.as_ref().is_some_and(|reader| {
+            reader
+                .send(Request {
+                    selection,
+                    targets,
+                    time,
+                    reply,
+                })
+                .is_ok()
+        });
+
+        async move {
+            if !sent {
+                return Err(Error::ReaderDisconnected);
+            }
+
+            conversion.await.map_err(|_| Error::ReaderDisconnected)?
+        }
+    }
+
+    fn read_text(
+        &self,
+        selection: Atom,
+        time: Timestamp,
+    ) -> impl Future<Output = Result<String, Error>> + Send + 'static {
+        let atoms = self.writer.atoms.clone();
+        let conversion = self.convert(selection, text::targets(&atoms), time);
+
+        async move {
+            match conversion.await? {
+                Some((_, kind, data)) => text::decode(&atoms, kind, data),
+                None => Err(Error::NoConversion),
+            }
+        }
+    }
+
+    fn read_selection_mime(
+        &self,
+        selection: Atom,
+        mimes: &[&str],
+    ) -> impl Future<Output = Result<(String, Vec<u8>), Error>> + Send + 'static
+    {
+        let mimes: Vec<String> =
+            mimes.iter().map(|&mime| mime.into()).collect();
+
+        let conversion = mimes
+            .iter()
+            .map(|mime| get_atom(&self.writer.connection, mime))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|targets| {
+                self.convert(selection, targets, x11rb::CURRENT_TIME)
+            });
+
+        async move {
+            match conversion?.await? {
+                Some((index, _, data)) => Ok((mimes[index].clone(), data)),
+                None => Err(Error::NoConversion),
+            }
+        }
+    }
+
+    /// The atom naming `selection`.
+    fn selection_atom(&self, selection: &Selection) -> Result<Atom, Error> {
+        let atoms = &self.writer.atoms;
+
+        match selection {
+            Selection::Clipboard => Ok(atoms.clipboard),
+            Selection::Primary => Ok(atoms.primary),
+            Selection::Secondary => Ok(atoms.secondary),
+            Selection::Named(name) => get_atom(&self.writer.connection, name),
+        }
+    }
+
+    /// Read the current value of `selection` as text.
+    ///
+    /// Fails with [`Error::Empty`] if nobody owns the selection, and with
+    /// [`Error::NoConversion`] if its owner cannot provide text.
+    pub fn read_selection(
+        &self,
+        selection: &Selection,
+    ) -> Result<String, Error> {
+        let selection = self.selection_atom(selection)?;
+
+        block_on(self.read_text(selection, x11rb::CURRENT_TIME))
     }
 
     /// Read the current CLIPBOARD [`Clipboard`] value.
+    ///
+    /// Fails with [`Error::Empty`] if nobody owns the selection, and with
+    /// [`Error::NoConversion`] if its owner cannot provide text.
     pub fn read(&self) -> Result<String, Error> {
-        self.read_selection(self.reader.atoms.clipboard)
+        block_on(self.read_async())
     }
 
+    /// Read the CLIPBOARD [`Clipboard`] value as of `time`.
+    ///
+    /// `time` should be the timestamp of the input event that caused the
+    /// read. Passing `CurrentTime` uses the current server time instead.
+    pub fn read_at(&self, time: Timestamp) -> Result<String, Error> {
+        block_on(self.read_text(self.writer.atoms.clipboard, time))
+    }
 
     /// Read the current PRIMARY [`Clipboard`] value.
     pub fn read_primary(&self) -> Result<String, Error> {
-        self.read_selection(self.reader.atoms.primary)
+        block_on(self.read_primary_async())
     }
 
-    fn write_selection(&mut self, selection: Atom, contents: String) -> Result<(), Error> {
-        let target = self.writer.atoms.utf8_string;
+    /// Read the current CLIPBOARD [`Clipboard`] value without blocking.
+    ///
+    /// The returned future completes once the owner has finished the
+    /// transfer. Dropping it cancels the transfer.
+    pub fn read_async(
+        &self,
+    ) -> impl Future<Output = Result<String, Error>> + Send + 'static {
+        self.read_text(self.writer.atoms.clipboard, x11rb::CURRENT_TIME)
+    }
 
-        self.selections
-            .write()
-            .map_err(|_| Error::SelectionLocked)?
-            .insert(selection, (target, contents.into()));
+    /// Read the current PRIMARY [`Clipboard`] value without blocking.
+    ///
+    /// The returned future completes once the owner has finished the
+    /// transfer. Dropping it cancels the transfer.
+    pub fn read_primary_async(
+        &self,
+    ) -> impl Future<Output = Result<String, Error>> + Send + 'static {
+        self.read_text(self.writer.atoms.primary, x11rb::CURRENT_TIME)
+    }
 
-        let _ = xproto::set_selection_owner(
-            &self.writer.connection,
-            self.writer.window,
+    fn available_targets(
+        &self,
+        selection: Atom,
+    ) -> Result<Vec<(String, Option<usize>)>, Error> {
+        let atoms = &self.writer.atoms;
+
+        let data = match block_on(self.convert(
             selection,
+            vec![atoms.targets],
             x11rb::CURRENT_TIME,
-        )?;
-
-        let _ = self.writer.connection.flush()?;
+        )) {
+            Ok(Some((_, _, data))) => data,
+            Ok(None) | Err(Error::Empty) => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
 
-        let reply =
-            xproto::get_selection_owner(&self.writer.connection, selection)
-                .map_err(Into::into)
-                .and_then(|cookie| cookie.reply())?;
+        let targets = to_u32s(&data);
 
-        if reply.owner == self.writer.window {
-            Ok(())
+        // LENGTH is the size of the selection as text, if the owner knows it.
+        let length = if targets.contains(&atoms.length) {
+            block_on(self.convert(
+                selection,
+                vec![atoms.length],
+                x11rb::CURRENT_TIME,
+            ))?
+            .and_then(|(_, _, data)| {
+                to_u32s(&data).first().map(|&length| length as usize)
+            })
         } else {
-            Err(Error::InvalidOwner)
+            None
+        };
+
+        let cookies = targets
+            .iter()
+            .filter(|&&target| !atoms.is_meta(target))
+            .map(|&target| {
+                xproto::get_atom_name(&self.writer.connection, target)
+                    .map(|cookie| (target, cookie))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        cookies
+            .into_iter()
+            .map(|(target, cookie)| {
+                let name =
+                    String::from_utf8_lossy(&cookie.reply()?.name).into_owned();
+
+                let is_text = target == atoms.utf8_string
+                    || target == atoms.string
+                    || name.starts_with("text/plain");
+
+                Ok((name, length.filter(|_| is_text)))
+            })
+            .collect()
+    }
+
+    /// List the targets the current CLIPBOARD owner can convert to, along
+    /// with a size hint when the owner provides one.
+    pub fn available_formats(
+        &self,
+    ) -> Result<Vec<(String, Option<usize>)>, Error> {
+        self.available_targets(self.writer.atoms.clipboard)
+    }
+
+    /// List the targets the current PRIMARY owner can convert to, along with
+    /// a size hint when the owner provides one.
+    pub fn available_primary_formats(
+        &self,
+    ) -> Result<Vec<(String, Option<usize>)>, Error> {
+        self.available_targets(self.writer.atoms.primary)
+    }
+
+    /// Read the CLIPBOARD [`Clipboard`] value as the first target in `mimes`
+    /// the owner is able to convert to.
+    ///
+    /// Returns the chosen target along with its data.
+    pub fn read_mime(
+        &self,
+        mimes: &[&str],
+    ) -> Result<(String, Vec<u8>), Error> {
+        block_on(self.read_mime_async(mimes))
+    }
+
+    /// Read the PRIMARY [`Clipboard`] value as the first target in `mimes` the
+    /// owner is able to convert to.
+    ///
+    /// Returns the chosen target along with its data.
+    pub fn read_primary_mime(
+        &self,
+        mimes: &[&str],
+    ) -> Result<(String, Vec<u8>), Error> {
+        block_on(self.read_primary_mime_async(mimes))
+    }
+
+    /// Like [`Clipboard::read_mime`], but without blocking.
+    ///
+    /// Dropping the returned future cancels the transfer.
+    pub fn read_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> impl Future<Output = Result<(String, Vec<u8>), Error>> + Send + 'static
+    {
+        self.read_selection_mime(self.writer.atoms.clipboard, mimes)
+    }
+
+    /// Like [`Clipboard::read_primary_mime`], but without blocking.
+    ///
+    /// Dropping the returned future cancels the transfer.
+    pub fn read_primary_mime_async(
+        &self,
+        mimes: &[&str],
+    ) -> impl Future<Output = Result<(String, Vec<u8>), Error>> + Send + 'static
+    {
+        self.read_selection_mime(self.writer.atoms.primary, mimes)
+    }
+
+    /// Take ownership of `selection` as of `time`, serving `contents`.
+    ///
+    /// The claim is made once the worker has noticed the server time, if
+    /// `time` is `CurrentTime`. The returned future resolves once the
+    /// ownership is confirmed.
+    fn claim(
+        &self,
+        selection: Atom,
+        contents: Vec<(Atom, Data)>,
+        time: Timestamp,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let server_time =
+            (time == x11rb::CURRENT_TIME).then(|| self.server_time());
+        let writer = Arc::clone(&self.writer);
+        let selections = Arc::clone(&self.selections);
+
+        async move {
+            let time = match server_time {
+                Some(server_time) => server_time.await?,
+                None => time,
+            };
+
+            // Recorded first, so that requests made as soon as we own the
+            // selection are answered.
+            let _ = selections
+                .write()
+                .map_err(|_| Error::SelectionLocked)?
+                .insert(selection, Owned { time, contents });
+
+            let result = writer.set_owner(selection, time);
+
+            // The worker must not answer for a selection we do not own.
+            if result.is_err() {
+                if let Ok(mut selections) = selections.write() {
+                    let _ = selections.remove(&selection);
+                }
+            }
+
+            result
+        }
+    }
+
+    /// Obtain the current server time, by appending nothing to a property
+    /// of our window and waiting for the worker to be notified of it.
+    ///
+    /// Owners must not use `CurrentTime`, as they would be unable to tell
+    /// which requests predate their ownership.
+    fn server_time(
+        &self,
+    ) -> impl Future<Output = Result<Timestamp, Error>> + Send + 'static {
+        let (sender, timestamp) = oneshot::channel();
+
+        // Waiting before asking, so that the answer cannot be missed.
+        let requested = self
+            .timestamps
+            .lock()
+            .map_err(|_| Error::SelectionLocked)
+            .and_then(|mut waiters| match waiters.as_mut() {
+                Some(waiters) => {
+                    waiters.push(sender);
+                    Ok(())
+                }
+                None => Err(Error::WorkerDisconnected),
+            })
+            .and_then(|()| self.writer.request_timestamp());
+
+        async move {
+            requested?;
+
+            timestamp.await.map_err(|_| Error::WorkerDisconnected)
+        }
+    }
+
+    fn write_selection_mime(
+        &self,
+        selection: Atom,
+        contents: Vec<(String, Data)>,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let claim = self
+            .targets(contents)
+            .map(|targets| self.claim(selection, targets, x11rb::CURRENT_TIME));
+
+        async move { claim?.await }
+    }
+
+    /// Turn `(mime, value)` pairs into the `(target, value)` pairs we serve,
+    /// adding the legacy targets of text.
+    fn targets(
+        &self,
+        contents: Vec<(String, Data)>,
+    ) -> Result<Vec<(Atom, Data)>, Error> {
+        let mut targets = Vec::with_capacity(contents.len() + 1);
+
+        for (mime, data) in contents {
+            if TEXT_MIME_TYPES.contains(&mime.as_str()) {
+                for (target, alias) in self.text_aliases(&data) {
+                    if !targets.iter().any(|&(existing, _)| existing == target)
+                    {
+                        targets.push((target, alias));
+                    }
+                }
+            }
+
+            let target = get_atom(&self.writer.connection, &mime)?;
+
+            targets.retain(|&(existing, _)| existing != target);
+            targets.push((target, data));
+        }
+
+        Ok(targets)
+    }
+
+    /// The targets text is served as, from UTF-8 `data`.
+    ///
+    /// Lazy data is only served as `UTF8_STRING`, as it cannot be checked
+    /// for validity up front.
+    fn text_aliases(&self, data: &Data) -> Vec<(Atom, Data)> {
+        let text = match data {
+            Data::Ready(bytes) => {
+                std::str::from_utf8(bytes).ok().map(Arc::from)
+            }
+            Data::Lazy(..) => None,
+            Data::Text(text) => Some(Arc::clone(text)),
+        };
+
+        match text {
+            Some(text) => text_contents(&self.writer.atoms, text),
+            None => vec![(self.writer.atoms.utf8_string, data.clone())],
         }
     }
 
     /// Write a new value to the CLIPBOARD [`Clipboard`].
     pub fn write(&mut self, contents: String) -> Result<(), Error> {
+        block_on(self.write_async(contents))
+    }
+
+    /// Write a new value to the CLIPBOARD [`Clipboard`], claiming it at
+    /// `time`.
+    ///
+    /// `time` should be the timestamp of the input event that caused the
+    /// write, so that it is ordered correctly with the requests of other
+    /// clients. Passing `CurrentTime` uses the current server time instead.
+    pub fn write_at(
+        &mut self,
+        contents: String,
+        time: Timestamp,
+    ) -> Result<(), Error> {
         let selection = self.writer.atoms.clipboard;
-        self.write_selection(selection, contents)
+        let contents = text_contents(&self.writer.atoms, Arc::from(contents));
+
+        block_on(self.claim(selection, contents, time))
     }
 
     /// Write a new value to the PRIMARY [`Clipboard`].
     pub fn write_primary(&mut self, contents: String) -> Result<(), Error> {
         let selection = self.writer.atoms.primary;
-        self.write_selection(selection, contents)
+        let contents = text_contents(&self.writer.atoms, Arc::from(contents));
+
+        block_on(self.claim(selection, contents, x11rb::CURRENT_TIME))
     }
 
-    /// load value.
-    fn load(
-        &self,
-        selection: Atom,
-        target: Atom,
-        property: Atom,
-        timeout: impl Into<Option<Duration>>,
-    ) -> Result<Vec<u8>, Error> {
-        let mut buff = Vec::new();
-        let timeout = timeout.into();
-
-        let _ = xproto::convert_selection(
-            &self.reader.connection,
-            self.reader.window,
-            selection,
-            target,
-            property,
-            x11rb::CURRENT_TIME, // FIXME ^
-                                 // Clients should not use CurrentTime for the time argument of a ConvertSelection request.
-                                 // Instead, they should use the timestamp of the event that caused the request to be made.
-        )?;
-        let _ = self.reader.connection.flush()?;
+    /// Write a new value to `selection`.
+    pub fn write_selection(
+        &mut self,
+        selection: &Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        let selection = self.selection_atom(selection)?;
+        let contents = text_contents(&self.writer.atoms, Arc::from(contents));
 
-        self.process_event(&mut buff, selection, target, property, timeout)?;
+        block_on(self.claim(selection, contents, x11rb::CURRENT_TIME))
+    }
 
-        let _ = xproto::delete_property(
-            &self.reader.connection,
-            self.reader.window,
-            property,
-        )?;
-        let _ = self.reader.connection.flush()?;
+    /// Write a new value to the CLIPBOARD [`Clipboard`], returning a future
+    /// that resolves once ownership has been confirmed.
+    ///
+    /// The selection is claimed as of the current server time, which the
+    /// background worker learns without blocking the caller.
+    pub fn write_async(
+        &mut self,
+        contents: String,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let selection = self.writer.atoms.clipboard;
+        let contents = text_contents(&self.writer.atoms, Arc::from(contents));
 
-        Ok(buff)
+        self.claim(selection, contents, x11rb::CURRENT_TIME)
     }
 
-    fn process_event<T>(
-        &self,
-        buff: &mut Vec<u8>,
-        selection: Atom,
-        target: Atom,
-        property: Atom,
-        timeout: T,
+    /// Write several representations of the same value to the CLIPBOARD
+    /// [`Clipboard`] at once.
+    ///
+    /// Each `(mime, data)` pair is served as the target named `mime`.
+    pub fn write_mime(
+        &mut self,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        let selection = self.writer.atoms.clipboard;
+        block_on(self.write_selection_mime(selection, from_pairs(contents)))
+    }
+
+    /// Write several representations of the same value to the PRIMARY
+    /// [`Clipboard`] at once.
+    ///
+    /// Each `(mime, data)` pair is served as the target named `mime`.
+    pub fn write_primary_mime(
+        &mut self,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        let selection = self.writer.atoms.primary;
+        block_on(self.write_selection_mime(selection, from_pairs(contents)))
+    }
+
+    /// Offer the CLIPBOARD [`Clipboard`] value as each target in `mimes`,
+    /// without producing it up front.
+    ///
+    /// `provider` is called from a background thread with the requested mime
+    /// type every time another client asks for it. Returning an error
+    /// refuses the conversion.
+    pub fn write_lazy<F>(
+        &mut self,
+        mimes: &[&str],
+        provider: F,
     ) -> Result<(), Error>
     where
-        T: Into<Option<Duration>>,
+        F: Fn(&str) -> Result<Vec<u8>, ProviderError> + Send + Sync + 'static,
     {
-        let mut is_incr = false;
-        let timeout = timeout.into();
-        let start_time = if timeout.is_some() {
-            Some(Instant::now())
-        } else {
-            None
-        };
-
-        loop {
-            if timeout
-                .into_iter()
-                .zip(start_time)
-                .next()
-                .map(|(timeout, time)| (Instant::now() - time) >= timeout)
-                .unwrap_or(false)
-            {
-                return Err(Error::Timeout);
-            }
+        let selection = self.writer.atoms.clipboard;
+        let contents = lazy(mimes, Arc::new(provider));
+        block_on(self.write_selection_mime(selection, contents))
+    }
 
-            let event = match self.reader.connection.poll_for_event()? {
-                Some(event) => event,
-                None => {
-                    thread::park_timeout(POLL_DURATION);
-                    continue;
-                }
-            };
+    /// Offer the PRIMARY [`Clipboard`] value as each target in `mimes`,
+    /// without producing it up front.
+    ///
+    /// See [`Clipboard::write_lazy`].
+    pub fn write_primary_lazy<F>(
+        &mut self,
+        mimes: &[&str],
+        provider: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Result<Vec<u8>, ProviderError> + Send + Sync + 'static,
+    {
+        let selection = self.writer.atoms.primary;
+        let contents = lazy(mimes, Arc::new(provider));
+        block_on(self.write_selection_mime(selection, contents))
+    }
 
-            match event {
-                Event::SelectionNotify(event) => {
-                    if event.selection != selection {
-                        continue;
-                    };
-
-                    // Note that setting the property argument to None indicates that the
-                    // conversion requested could not be made.
-                    if event.property == AtomEnum::NONE.into() {
-                        break;
-                    }
+    /// Like [`Clipboard::write_mime`], but returning a future.
+    ///
+    /// See [`Clipboard::write_async`].
+    pub fn write_mime_async(
+        &mut self,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let selection = self.writer.atoms.clipboard;
+        self.write_selection_mime(selection, from_pairs(contents))
+    }
 
-                    let reply = xproto::get_property(
-                        &self.reader.connection,
-                        false,
-                        self.reader.window,
-                        event.property,
-                        Atom::from(AtomEnum::ANY),
-                        buff.len() as u32,
-                        ::std::u32::MAX, // FIXME reasonable buffer size
-                    )
-                    .map_err(Into::into)
-                    .and_then(|cookie| cookie.reply())?;
-
-                    if reply.type_ == self.reader.atoms.incr {
-                        if let Some(&size) = reply.value.get(0) {
-                            buff.reserve(size as usize);
-                        }
+    /// Hand the CLIPBOARD value we own over to the clipboard manager, so it
+    /// stays available once we exit.
+    ///
+    /// Call this right before exiting. It blocks until the manager has copied
+    /// every target, or until the read timeout of the [`Settings`] passes.
+    /// Nothing is done if we do not own the CLIPBOARD selection.
+    ///
+    /// Fails with [`Error::NoClipboardManager`] if no clipboard manager is
+    /// running, and with [`Error::NoConversion`] if it refused the value.
+    pub fn store_on_exit(&self) -> Result<(), Error> {
+        let atoms = &self.writer.atoms;
 
-                        let _ = xproto::delete_property(
-                            &self.reader.connection,
-                            self.reader.window,
-                            property,
-                        );
+        let is_owned = self
+            .selections
+            .read()
+            .map_err(|_| Error::SelectionLocked)?
+            .contains_key(&atoms.clipboard);
 
-                        let _ = self.reader.connection.flush();
-                        is_incr = true;
+        if !is_owned {
+            return Ok(());
+        }
 
-                        continue;
-                    } else if reply.type_ != target {
-                        return Err(Error::UnexpectedType(reply.type_));
-                    }
+        // The manager converts the selection to every target we offer while
+        // we wait, so the worker serves it as it would any other client.
+        let conversion = self.convert(
+            atoms.clipboard_manager,
+            vec![atoms.save_targets],
+            x11rb::CURRENT_TIME,
+        );
 
-                    buff.extend_from_slice(&reply.value);
-                    break;
-                }
-                Event::PropertyNotify(event) if is_incr => {
-                    if event.state != xproto::Property::NEW_VALUE {
-                        continue;
-                    };
-
-                    let length = xproto::get_property(
-                        &self.reader.connection,
-                        false,
-                        self.reader.window,
-                        property,
-                        Atom::from(AtomEnum::ANY),
-                        0,
-                        0,
-                    )
-                    .map_err(Into::into)
-                    .and_then(|cookie| cookie.reply())?
-                    .bytes_after;
-
-                    let reply = xproto::get_property(
-                        &self.reader.connection,
-                        true,
-                        self.reader.window,
-                        property,
-                        Atom::from(AtomEnum::ANY),
-                        0,
-                        length,
-                    )
-                    .map_err(Into::into)
-                    .and_then(|cookie| cookie.reply())?;
-
-                    if reply.type_ != target {
-                        continue;
-                    };
-
-                    if reply.value_len != 0 {
-                        buff.extend_from_slice(&reply.value);
-                    } else {
-                        break;
-                    }
-                }
-                _ => {}
-            }
+        match block_on(conversion) {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(Error::NoConversion),
+            Err(Error::Empty) => Err(Error::NoClipboardManager),
+            Err(error) => Err(error),
         }
+    }
+}
 
-        Ok(())
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
     }
 }
 
@@ -284,12 +824,100 @@ pub struct Context {
 #[derive(Clone, Debug)]
 pub struct Atoms {
     pub primary: Atom,
+    pub secondary: Atom,
     pub clipboard: Atom,
     pub property: Atom,
     pub targets: Atom,
+    pub length: Atom,
+    pub multiple: Atom,
+    pub timestamp: Atom,
     pub string: Atom,
     pub utf8_string: Atom,
     pub incr: Atom,
+    pub clipboard_manager: Atom,
+    pub save_targets: Atom,
+    pub text: Atom,
+    pub compound_text: Atom,
+    pub text_plain: Atom,
+    pub text_plain_utf8: Atom,
+    pub text_plain_utf16: Atom,
+}
+
+impl Atoms {
+    /// The type of the property an owner stores a conversion to `target` in.
+    fn property_type(&self, target: Atom) -> Atom {
+        if target == self.targets {
+            AtomEnum::ATOM.into()
+        } else if target == self.length {
+            AtomEnum::INTEGER.into()
+        } else {
+            target
+        }
+    }
+
+    /// Returns `true` if an owner may store a conversion to `target` in a
+    /// property of type `kind`.
+    fn accepts(&self, target: Atom, kind: Atom) -> bool {
+        if target == self.text {
+            // The owner picks one of these.
+            [self.string, self.compound_text, self.utf8_string].contains(&kind)
+        } else {
+            kind == self.property_type(target)
+        }
+    }
+
+    /// Returns `true` if converting to `target` is only done for its side
+    /// effects, so the owner sends no value back.
+    fn is_side_effect(&self, target: Atom) -> bool {
+        target == self.save_targets
+    }
+
+    /// Returns `true` if `target` describes the selection rather than
+    /// converting its value.
+    fn is_meta(&self, target: Atom) -> bool {
+        [self.targets, self.length, self.multiple, self.timestamp]
+            .contains(&target)
+    }
+}
+
+fn ready(contents: impl Into<Vec<u8>>) -> Data {
+    Data::Ready(Arc::from(contents.into()))
+}
+
+/// Serve `text` as every text target.
+fn text_contents(atoms: &Atoms, text: Arc<str>) -> Vec<(Atom, Data)> {
+    text::targets(atoms)
+        .into_iter()
+        .map(|target| (target, Data::Text(Arc::clone(&text))))
+        .collect()
+}
+
+fn from_pairs(contents: Vec<(String, Vec<u8>)>) -> Vec<(String, Data)> {
+    contents
+        .into_iter()
+        .map(|(mime, data)| (mime, ready(data)))
+        .collect()
+}
+
+fn lazy(mimes: &[&str], provider: Provider) -> Vec<(String, Data)> {
+    mimes
+        .iter()
+        .map(|&mime| {
+            (
+                mime.to_owned(),
+                Data::Lazy(mime.to_owned(), provider.clone()),
+            )
+        })
+        .collect()
+}
+
+/// Decodes the value of a property in 32-bit format.
+fn to_u32s(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .map(|chunk| {
+            u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])
+        })
+        .collect()
 }
 
 #[inline]
@@ -301,7 +929,114 @@ fn get_atom(connection: &Connection, name: &str) -> Result<Atom, Error> {
         .map_err(Into::into)
 }
 
+/// Drive `future` to completion, parking the current thread while it is
+/// pending.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unparker(thread::Thread);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unparker(thread::current())));
+    let mut context = task::Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
 impl Context {
+    /// Ask the server for XFixes notifications about the ownership of the
+    /// CLIPBOARD and PRIMARY selections.
+    ///
+    /// Returns `false` if the XFixes extension is not available.
+    fn select_selection_changes(&self) -> Result<bool, Error> {
+        if self
+            .connection
+            .extension_information(xfixes::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Ok(false);
+        }
+
+        let _ = self.connection.xfixes_query_version(5, 0)?.reply()?;
+
+        for selection in [self.atoms.clipboard, self.atoms.primary] {
+            let _ = self.connection.xfixes_select_selection_input(
+                self.window,
+                selection,
+                xfixes::SelectionEventMask::SET_SELECTION_OWNER
+                    | xfixes::SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | xfixes::SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )?;
+        }
+
+        self.connection.flush()?;
+
+        Ok(true)
+    }
+
+    /// Ask the server for its current time, by appending nothing to a
+    /// property of our window.
+    ///
+    /// The time is sent back in a `PropertyNotify` event, as recognized by
+    /// [`Context::is_timestamp`].
+    fn request_timestamp(&self) -> Result<(), Error> {
+        let _ = xproto::change_property(
+            &self.connection,
+            xproto::PropMode::APPEND,
+            self.window,
+            self.atoms.timestamp,
+            AtomEnum::INTEGER,
+            32,
+            0,
+            &[],
+        )?;
+
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    /// Returns `true` if `event` answers [`Context::request_timestamp`].
+    fn is_timestamp(&self, event: &PropertyNotifyEvent) -> bool {
+        event.window == self.window
+            && event.atom == self.atoms.timestamp
+            && event.state == xproto::Property::NEW_VALUE
+    }
+
+    /// Make our window the owner of `selection` as of `time`.
+    ///
+    /// Fails with [`Error::InvalidOwner`] if the server refused, for
+    /// instance because `time` predates the latest change of owner.
+    fn set_owner(&self, selection: Atom, time: Timestamp) -> Result<(), Error> {
+        let _ = xproto::set_selection_owner(
+            &self.connection,
+            self.window,
+            selection,
+            time,
+        )?;
+
+        self.connection.flush()?;
+
+        let reply = xproto::get_selection_owner(&self.connection, selection)
+            .map_err(Into::into)
+            .and_then(|cookie| cookie.reply())?;
+
+        if reply.owner == self.window {
+            Ok(())
+        } else {
+            Err(Error::InvalidOwner)
+        }
+    }
+
     pub fn new(displayname: Option<&str>) -> Result<Self, Error> {
         let (connection, screen) = Connection::connect(displayname)?;
         let window = connection.generate_id().map_err(|_| {
@@ -310,7 +1045,7 @@ impl Context {
 
         {
             let screen =
-                connection.setup().roots.get(screen as usize).ok_or(
+                connection.setup().roots.get(screen).ok_or(
                     Error::ConnectionFailed(ConnectError::InvalidScreen),
                 )?;
 
@@ -332,17 +1067,31 @@ impl Context {
                 ),
             )?;
 
-            let _ = connection.flush()?;
+            connection.flush()?;
         }
 
         let atoms = Atoms {
             primary: AtomEnum::PRIMARY.into(),
+            secondary: AtomEnum::SECONDARY.into(),
             clipboard: get_atom(&connection, "CLIPBOARD")?,
             property: get_atom(&connection, "THIS_CLIPBOARD_OUT")?,
             targets: get_atom(&connection, "TARGETS")?,
+            length: get_atom(&connection, "LENGTH")?,
+            multiple: get_atom(&connection, "MULTIPLE")?,
+            timestamp: get_atom(&connection, "TIMESTAMP")?,
             string: AtomEnum::STRING.into(),
             utf8_string: get_atom(&connection, "UTF8_STRING")?,
             incr: get_atom(&connection, "INCR")?,
+            clipboard_manager: get_atom(&connection, "CLIPBOARD_MANAGER")?,
+            save_targets: get_atom(&connection, "SAVE_TARGETS")?,
+            text: get_atom(&connection, "TEXT")?,
+            compound_text: get_atom(&connection, "COMPOUND_TEXT")?,
+            text_plain: get_atom(&connection, "text/plain")?,
+            text_plain_utf8: get_atom(&connection, "text/plain;charset=utf-8")?,
+            text_plain_utf16: get_atom(
+                &connection,
+                "text/plain;charset=utf-16",
+            )?,
         };
 
         Ok(Context {
@@ -356,80 +1105,468 @@ impl Context {
 
 pub struct Worker {
     context: Arc<Context>,
-    selections: Arc<RwLock<HashMap<Atom, (Atom, Vec<u8>)>>>,
+    selections: Arc<RwLock<Selections>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    /// The INCR transfers in progress, by requestor window and property.
+    transfers: HashMap<(Window, Atom), Transfer>,
+    incr_chunk_size: usize,
+    timestamps: Timestamps,
+    errors: Arc<Mutex<Vec<Error>>>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Nobody is left to notice server times.
+        if let Ok(mut waiters) = self.timestamps.lock() {
+            *waiters = None;
+        }
+    }
+}
+
+/// A value sent in chunks to a requestor, as per the INCR protocol.
+struct Transfer {
+    /// The type of the property the chunks are stored in.
+    kind: Atom,
+    value: Arc<[u8]>,
+    /// How much of the value has been sent so far.
+    offset: usize,
 }
 
 impl Worker {
-    pub const INCR_CHUNK_SIZE: usize = 4000;
+    /// The size of a `ChangeProperty` request without its data.
+    const CHANGE_PROPERTY_HEADER_SIZE: usize = 24;
+
+    /// How many errors are kept for [`Clipboard::take_errors`].
+    pub const MAX_ERRORS: usize = 16;
+
+    pub fn run(mut self) {
+        loop {
+            let event = match self.context.connection.wait_for_event() {
+                Ok(event) => event,
+                Err(error) => {
+                    self.report(error.into());
+                    break;
+                }
+            };
 
-    pub fn run(self) {
-        while let Ok(event) = self.context.connection.wait_for_event() {
             match event {
                 Event::SelectionRequest(event) => {
-                    let selections = match self.selections.read().ok() {
-                        Some(selections) => selections,
-                        None => continue,
-                    };
-
-                    let &(target, ref value) =
-                        match selections.get(&event.selection) {
-                            Some(key_value) => key_value,
-                            None => continue,
-                        };
-
-                    if event.target == self.context.atoms.targets {
-                        let data = [self.context.atoms.targets, target];
-
-                        self.context
-                            .connection
-                            .change_property32(
-                                xproto::PropMode::REPLACE,
-                                event.requestor,
-                                event.property,
-                                xproto::AtomEnum::ATOM,
-                                &data,
-                            )
-                            .expect("Change property");
-                    } else {
-                        let _ = self
-                            .context
-                            .connection
-                            .change_property8(
-                                xproto::PropMode::REPLACE,
-                                event.requestor,
-                                event.property,
-                                target,
-                                value,
-                            )
-                            .expect("Change property");
+                    if let Err(error) = self.answer(&event) {
+                        self.report(error);
                     }
+                }
+                // A timestamp asked for by `Clipboard::server_time`.
+                Event::PropertyNotify(event)
+                    if self.context.is_timestamp(&event) =>
+                {
+                    let waiters = self
+                        .timestamps
+                        .lock()
+                        .ok()
+                        .and_then(|mut waiters| waiters.as_mut().map(mem::take))
+                        .unwrap_or_default();
 
-                    let _ = xproto::send_event(
-                        &self.context.connection,
-                        false,
-                        event.requestor,
-                        EventMask::NO_EVENT,
-                        xproto::SelectionNotifyEvent {
-                            response_type: 31,
-                            sequence: event.sequence,
-                            time: event.time,
-                            requestor: event.requestor,
-                            selection: event.selection,
-                            target: event.target,
-                            property: event.property,
-                        },
-                    )
-                    .expect("Send event");
-
-                    let _ = self.context.connection.flush();
+                    for waiter in waiters {
+                        let _ = waiter.send(event.time);
+                    }
+                }
+                Event::PropertyNotify(event)
+                    if event.state == xproto::Property::DELETE =>
+                {
+                    if let Err(error) =
+                        self.continue_transfer(event.window, event.atom)
+                    {
+                        self.report(error);
+                    }
+                }
+                // Our window is destroyed when the clipboard is shut down.
+                Event::DestroyNotify(event)
+                    if event.window == self.context.window =>
+                {
+                    break;
+                }
+                Event::DestroyNotify(event) => {
+                    // The requestor is gone, along with its transfers.
+                    self.transfers
+                        .retain(|&(window, _), _| window != event.window);
                 }
                 Event::SelectionClear(event) => {
                     if let Ok(mut write_setmap) = self.selections.write() {
                         write_setmap.remove(&event.selection);
                     }
+
+                    match self.selection(event.selection) {
+                        Ok(selection) => self
+                            .notify(ClipboardEvent::OwnershipLost(selection)),
+                        Err(error) => self.report(error),
+                    }
+                }
+                Event::XfixesSelectionNotify(event) => {
+                    match self.selection(event.selection) {
+                        Ok(selection) => {
+                            self.notify(ClipboardEvent::Changed(selection))
+                        }
+                        Err(error) => self.report(error),
+                    }
+                }
+                // Errors of requests we do not wait for the reply of, most
+                // likely about a requestor that went away.
+                Event::Error(error) => {
+                    self.report(Error::ReplyError(error.into()));
                 }
                 _ => (),
             }
         }
     }
+
+    /// Convert a selection we own for a requestor, then notify it of the
+    /// outcome.
+    ///
+    /// Every request is answered, refusing the ones that cannot be converted
+    /// with a `None` property.
+    fn answer(&mut self, event: &SelectionRequestEvent) -> Result<(), Error> {
+        // Obsolete clients do not name a property, and expect the value to
+        // be stored in the one named after the target.
+        let property = if event.property == x11rb::NONE {
+            event.target
+        } else {
+            event.property
+        };
+
+        let owned = self
+            .selections
+            .read()
+            .map_err(|_| Error::SelectionLocked)?
+            .get(&event.selection)
+            .cloned()
+            // Requests made before we acquired the selection are refused.
+            .filter(|owned| {
+                event.time == x11rb::CURRENT_TIME
+                    || owned.time == x11rb::CURRENT_TIME
+                    || event.time >= owned.time
+            });
+
+        let converted = match owned {
+            Some(owned) if event.target == self.context.atoms.multiple => {
+                self.convert_multiple(event, &owned)
+            }
+            Some(owned) => {
+                self.convert(event.requestor, event.target, property, &owned)
+            }
+            None => Ok(false),
+        };
+
+        // Failed conversions are refused like any other.
+        let converted = converted.unwrap_or_else(|error| {
+            self.report(error);
+            false
+        });
+
+        let _ = xproto::send_event(
+            &self.context.connection,
+            false,
+            event.requestor,
+            EventMask::NO_EVENT,
+            xproto::SelectionNotifyEvent {
+                response_type: xproto::SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: event.time,
+                requestor: event.requestor,
+                selection: event.selection,
+                target: event.target,
+                property: if converted { property } else { x11rb::NONE },
+            },
+        )?;
+
+        self.context.connection.flush()?;
+
+        Ok(())
+    }
+
+    /// Store the value of `target` in the `property` of the `requestor`.
+    ///
+    /// Returns `false` if we cannot convert the selection to `target`.
+    fn convert(
+        &mut self,
+        requestor: Window,
+        target: Atom,
+        property: Atom,
+        owned: &Owned,
+    ) -> Result<bool, Error> {
+        let atoms = &self.context.atoms;
+
+        if target == atoms.targets {
+            let targets: Vec<Atom> =
+                [atoms.targets, atoms.multiple, atoms.timestamp]
+                    .iter()
+                    .copied()
+                    .chain(owned.contents.iter().map(|&(target, _)| target))
+                    .collect();
+
+            let _ = self.context.connection.change_property32(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                AtomEnum::ATOM,
+                &targets,
+            )?;
+
+            return Ok(true);
+        }
+
+        if target == atoms.timestamp {
+            let _ = self.context.connection.change_property32(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                AtomEnum::INTEGER,
+                &[owned.time],
+            )?;
+
+            return Ok(true);
+        }
+
+        let data = owned
+            .contents
+            .iter()
+            .find(|&&(candidate, _)| candidate == target)
+            .map(|(_, data)| data);
+
+        // The type of the property, which only differs from the target for
+        // some text targets.
+        let (kind, value) = match data {
+            Some(Data::Ready(value)) => (target, Arc::clone(value)),
+            Some(Data::Lazy(mime, provider)) => match provider(mime) {
+                Ok(value) => (target, Arc::from(value)),
+                // The provider failing only refuses the conversion.
+                Err(_) => return Ok(false),
+            },
+            Some(Data::Text(text)) => match text::encode(atoms, target, text) {
+                Some((kind, value)) => (kind, Arc::from(value)),
+                None => return Ok(false),
+            },
+            None => return Ok(false),
+        };
+
+        if value.len() > self.maximum_property_size() {
+            self.start_transfer(requestor, kind, property, value)?;
+        } else {
+            let _ = self.context.connection.change_property8(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                kind,
+                &value,
+            )?;
+        }
+
+        Ok(true)
+    }
+
+    /// Perform each of the conversions listed as `(target, property)` pairs
+    /// in the property of a `MULTIPLE` request.
+    ///
+    /// The property of every pair that could not be converted is replaced
+    /// with `None`, as per the ICCCM.
+    fn convert_multiple(
+        &mut self,
+        event: &SelectionRequestEvent,
+        owned: &Owned,
+    ) -> Result<bool, Error> {
+        // The conversions are named in the property, so there must be one.
+        if event.property == x11rb::NONE {
+            return Ok(false);
+        }
+
+        let reply = xproto::get_property(
+            &self.context.connection,
+            false,
+            event.requestor,
+            event.property,
+            AtomEnum::ANY,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+
+        let mut pairs: Vec<Atom> = match reply.value32() {
+            Some(pairs) => pairs.collect(),
+            None => return Ok(false),
+        };
+
+        let mut refused = false;
+
+        for pair in pairs.chunks_exact_mut(2) {
+            let (target, property) = (pair[0], pair[1]);
+
+            let converted = target != self.context.atoms.multiple
+                && property != x11rb::NONE
+                && self
+                    .convert(event.requestor, target, property, owned)
+                    .unwrap_or_else(|error| {
+                        self.report(error);
+                        false
+                    });
+
+            if !converted {
+                pair[1] = x11rb::NONE;
+                refused = true;
+            }
+        }
+
+        if refused {
+            let _ = self.context.connection.change_property32(
+                xproto::PropMode::REPLACE,
+                event.requestor,
+                event.property,
+                reply.type_,
+                &pairs,
+            )?;
+        }
+
+        Ok(true)
+    }
+
+    /// Keep an error for [`Clipboard::take_errors`].
+    fn report(&self, error: Error) {
+        if let Ok(mut errors) = self.errors.lock() {
+            if errors.len() == Self::MAX_ERRORS {
+                let _ = errors.remove(0);
+            }
+
+            errors.push(error);
+        }
+    }
+
+    /// The largest value that fits in a single `ChangeProperty` request.
+    ///
+    /// This takes the BIG-REQUESTS extension into account, when available.
+    fn maximum_property_size(&self) -> usize {
+        self.context
+            .connection
+            .maximum_request_bytes()
+            .saturating_sub(Self::CHANGE_PROPERTY_HEADER_SIZE)
+    }
+
+    /// Start sending `value` to a requestor in chunks.
+    ///
+    /// The requestor deletes the property each time it has read a chunk,
+    /// which is our cue to send the next one.
+    fn start_transfer(
+        &mut self,
+        requestor: Window,
+        kind: Atom,
+        property: Atom,
+        value: Arc<[u8]>,
+    ) -> Result<(), Error> {
+        // Watch the requestor for deleted properties, and for its
+        // destruction in case it never finishes the transfer.
+        let _ = xproto::change_window_attributes(
+            &self.context.connection,
+            requestor,
+            &xproto::ChangeWindowAttributesAux::new().event_mask(
+                EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY,
+            ),
+        )?;
+
+        // The value of an INCR property is a lower bound on the size of the
+        // whole value.
+        let _ = self.context.connection.change_property32(
+            xproto::PropMode::REPLACE,
+            requestor,
+            property,
+            self.context.atoms.incr,
+            &[value.len().min(u32::MAX as usize) as u32],
+        )?;
+
+        let _ = self.transfers.insert(
+            (requestor, property),
+            Transfer {
+                kind,
+                value,
+                offset: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Send the next chunk of the transfer to `window` through `property`,
+    /// if there is one in progress.
+    ///
+    /// A final empty chunk marks the end of the transfer.
+    fn continue_transfer(
+        &mut self,
+        window: Window,
+        property: Atom,
+    ) -> Result<(), Error> {
+        // Larger chunks would not fit in a request.
+        let chunk_size = self
+            .incr_chunk_size
+            .min(self.maximum_property_size())
+            .max(1);
+
+        let transfer = match self.transfers.get_mut(&(window, property)) {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+
+        let end = (transfer.offset + chunk_size).min(transfer.value.len());
+        let chunk = &transfer.value[transfer.offset..end];
+        let is_last = chunk.is_empty();
+
+        let _ = self.context.connection.change_property8(
+            xproto::PropMode::REPLACE,
+            window,
+            property,
+            transfer.kind,
+            chunk,
+        )?;
+
+        transfer.offset = end;
+
+        if is_last {
+            let _ = self.transfers.remove(&(window, property));
+
+            // Stop watching the requestor once it has nothing left to read.
+            if !self.transfers.keys().any(|&(other, _)| other == window) {
+                let _ = xproto::change_window_attributes(
+                    &self.context.connection,
+                    window,
+                    &xproto::ChangeWindowAttributesAux::new()
+                        .event_mask(EventMask::NO_EVENT),
+                )?;
+            }
+        }
+
+        self.context.connection.flush()?;
+
+        Ok(())
+    }
+
+    fn selection(&self, atom: Atom) -> Result<Selection, Error> {
+        let atoms = &self.context.atoms;
+
+        if atom == atoms.clipboard {
+            Ok(Selection::Clipboard)
+        } else if atom == atoms.primary {
+            Ok(Selection::Primary)
+        } else if atom == atoms.secondary {
+            Ok(Selection::Secondary)
+        } else {
+            let name = xproto::get_atom_name(&self.context.connection, atom)?
+                .reply()?
+                .name;
+
+            Ok(Selection::Named(
+                String::from_utf8_lossy(&name).into_owned(),
+            ))
+        }
+    }
+
+    fn notify(&self, event: ClipboardEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain_mut(|subscriber| subscriber(event.clone()));
+        }
+    }
 }
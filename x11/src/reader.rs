@@ -0,0 +1,425 @@
+use crate::{Context, Error, Settings};
+
+use futures_channel::oneshot;
+use rustix::event::{self, PollFd, PollFlags, Timespec};
+use rustix::fd::OwnedFd;
+use rustix::io::Errno;
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{self, Atom, AtomEnum, Timestamp};
+use x11rb::protocol::Event;
+
+use std::convert::TryFrom;
+use std::sync::{mpsc, Arc};
+use std::task::{self, Wake, Waker};
+use std::thread;
+use std::time::Instant;
+
+/// The result of a [`Request`]: the index of the target the owner converted
+/// the selection to along with the type and data of the value, or `None` if
+/// it refused them all.
+pub type Conversion = Result<Option<(usize, Atom, Vec<u8>)>, Error>;
+
+/// A request to convert a selection to the first of `targets` its owner
+/// supports.
+pub struct Request {
+    pub selection: Atom,
+    pub targets: Vec<Atom>,
+    /// The time of the event that caused the request, or `CurrentTime` to
+    /// use the current server time.
+    pub time: Timestamp,
+    pub reply: oneshot::Sender<Conversion>,
+}
+
+/// Performs conversions on a thread of its own, so they can be awaited.
+pub struct Reader {
+    context: Context,
+    settings: Settings,
+    requests: mpsc::Receiver<Request>,
+    /// The read end of the pipe [`Wakeup`] writes to.
+    wakeup: OwnedFd,
+    /// Wakes the reader up when the caller of a conversion goes away.
+    waker: Waker,
+}
+
+/// Wakes the [`Reader`] up while it waits for the X server, by writing to a
+/// pipe it polls along with the connection.
+struct Wakeup(OwnedFd);
+
+impl Wake for Wakeup {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        // A full pipe already wakes the reader up.
+        let _ = rustix::io::write(&self.0, &[0]);
+    }
+}
+
+impl Reader {
+    /// Spawn a [`Reader`] owning `context`, returning the channel to send it
+    /// requests through.
+    ///
+    /// The thread stops once every sender has been dropped.
+    pub fn spawn(
+        context: Context,
+        settings: Settings,
+    ) -> Result<(mpsc::Sender<Request>, thread::JoinHandle<()>), Error> {
+        let (wakeup, notifier) = rustix::pipe::pipe().map_err(io_error)?;
+        rustix::io::ioctl_fionbio(&wakeup, true).map_err(io_error)?;
+        rustix::io::ioctl_fionbio(&notifier, true).map_err(io_error)?;
+
+        let (sender, requests) = mpsc::channel();
+        let reader = Reader {
+            context,
+            settings,
+            requests,
+            wakeup,
+            waker: Waker::from(Arc::new(Wakeup(notifier))),
+        };
+
+        let thread = thread::spawn(move || reader.run());
+
+        Ok((sender, thread))
+    }
+
+    fn run(self) {
+        while let Ok(mut request) = self.requests.recv() {
+            if request.reply.is_canceled() {
+                continue;
+            }
+
+            let conversion = self.convert(&mut request);
+            let _ = request.reply.send(conversion);
+        }
+
+        let _ = xproto::destroy_window(
+            &self.context.connection,
+            self.context.window,
+        );
+        let _ = self.context.connection.flush();
+    }
+
+    fn convert(&self, request: &mut Request) -> Conversion {
+        let owner = xproto::get_selection_owner(
+            &self.context.connection,
+            request.selection,
+        )?
+        .reply()?
+        .owner;
+
+        if owner == x11rb::NONE {
+            return Err(Error::Empty);
+        }
+
+        let time = if request.time == x11rb::CURRENT_TIME {
+            match self.server_time(&mut request.reply)? {
+                Some(time) => time,
+                None => return Ok(None),
+            }
+        } else {
+            request.time
+        };
+
+        let mut unexpected = None;
+
+        for (index, &target) in request.targets.iter().enumerate() {
+            // Nobody is waiting for the result anymore.
+            if request.reply.is_canceled() {
+                return Ok(None);
+            }
+
+            match self.load(
+                request.selection,
+                target,
+                self.context.atoms.property,
+                time,
+                &mut request.reply,
+            ) {
+                Ok(Some((kind, data))) => {
+                    return Ok(Some((index, kind, data)));
+                }
+                Ok(None) => {}
+                // The owner answered with something else than what we asked
+                // for, which is as good as a refusal: try the next target.
+                Err(error @ Error::UnexpectedType(_)) => {
+                    unexpected = Some(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        match unexpected {
+            Some(error) => Err(error),
+            None => Ok(None),
+        }
+    }
+
+    /// load value, along with its type.
+    ///
+    /// Returns `None` if the owner could not convert the selection to
+    /// `target`, or if `caller` stopped waiting for the result.
+    fn load(
+        &self,
+        selection: Atom,
+        target: Atom,
+        property: Atom,
+        time: Timestamp,
+        caller: &mut oneshot::Sender<Conversion>,
+    ) -> Result<Option<(Atom, Vec<u8>)>, Error> {
+        let mut buff = Vec::new();
+
+        let _ = xproto::convert_selection(
+            &self.context.connection,
+            self.context.window,
+            selection,
+            target,
+            property,
+            time,
+        )?;
+        self.context.connection.flush()?;
+
+        let converted =
+            self.process_event(&mut buff, selection, target, property, caller);
+
+        // The property is deleted even if the transfer failed or was
+        // cancelled, so a stale value is never mistaken for the next one.
+        let _ = xproto::delete_property(
+            &self.context.connection,
+            self.context.window,
+            property,
+        )?;
+        self.context.connection.flush()?;
+
+        Ok(converted?.map(|kind| (kind, buff)))
+    }
+
+    fn process_event(
+        &self,
+        buff: &mut Vec<u8>,
+        selection: Atom,
+        target: Atom,
+        property: Atom,
+        caller: &mut oneshot::Sender<Conversion>,
+    ) -> Result<Option<Atom>, Error> {
+        let atoms = &self.context.atoms;
+        let mut is_incr = false;
+        let mut kind = x11rb::NONE;
+        let deadline = Instant::now().checked_add(self.settings.read_timeout);
+
+        loop {
+            let event = match self.next_event(deadline, caller)? {
+                Some(event) => event,
+                None => return Ok(None),
+            };
+
+            match event {
+                Event::SelectionNotify(event) => {
+                    if event.selection != selection || event.target != target {
+                        continue;
+                    };
+
+                    // Note that setting the property argument to None indicates that the
+                    // conversion requested could not be made.
+                    if event.property == AtomEnum::NONE.into() {
+                        return Ok(None);
+                    }
+
+                    if atoms.is_side_effect(target) {
+                        break;
+                    }
+
+                    let reply = xproto::get_property(
+                        &self.context.connection,
+                        false,
+                        self.context.window,
+                        event.property,
+                        Atom::from(AtomEnum::ANY),
+                        buff.len() as u32,
+                        self.max_length(),
+                    )
+                    .map_err(Into::into)
+                    .and_then(|cookie| cookie.reply())?;
+
+                    if reply.type_ == atoms.incr {
+                        if let Some(size) =
+                            reply.value32().and_then(|mut values| values.next())
+                        {
+                            self.check_size(size as usize)?;
+                            buff.reserve(size as usize);
+                        }
+
+                        let _ = xproto::delete_property(
+                            &self.context.connection,
+                            self.context.window,
+                            property,
+                        );
+
+                        let _ = self.context.connection.flush();
+                        is_incr = true;
+
+                        continue;
+                    } else if !atoms.accepts(target, reply.type_) {
+                        return Err(Error::UnexpectedType(reply.type_));
+                    }
+
+                    kind = reply.type_;
+
+                    self.check_size(
+                        reply.value.len() + reply.bytes_after as usize,
+                    )?;
+
+                    buff.extend_from_slice(&reply.value);
+                    break;
+                }
+                Event::PropertyNotify(event) if is_incr => {
+                    if event.state != xproto::Property::NEW_VALUE
+                        || event.atom != property
+                    {
+                        continue;
+                    };
+
+                    let length = xproto::get_property(
+                        &self.context.connection,
+                        false,
+                        self.context.window,
+                        property,
+                        Atom::from(AtomEnum::ANY),
+                        0,
+                        0,
+                    )
+                    .map_err(Into::into)
+                    .and_then(|cookie| cookie.reply())?
+                    .bytes_after;
+
+                    self.check_size(buff.len() + length as usize)?;
+
+                    let reply = xproto::get_property(
+                        &self.context.connection,
+                        true,
+                        self.context.window,
+                        property,
+                        Atom::from(AtomEnum::ANY),
+                        0,
+                        length,
+                    )
+                    .map_err(Into::into)
+                    .and_then(|cookie| cookie.reply())?;
+
+                    if !atoms.accepts(target, reply.type_) {
+                        continue;
+                    };
+
+                    kind = reply.type_;
+
+                    if reply.value_len != 0 {
+                        buff.extend_from_slice(&reply.value);
+                    } else {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(kind))
+    }
+
+    /// Obtain the current server time, as the ICCCM recommends when no event
+    /// caused a conversion.
+    ///
+    /// Returns `None` if `caller` stopped waiting for the result.
+    fn server_time(
+        &self,
+        caller: &mut oneshot::Sender<Conversion>,
+    ) -> Result<Option<Timestamp>, Error> {
+        self.context.request_timestamp()?;
+
+        let deadline = Instant::now().checked_add(self.settings.read_timeout);
+
+        while let Some(event) = self.next_event(deadline, caller)? {
+            if let Event::PropertyNotify(event) = event {
+                if self.context.is_timestamp(&event) {
+                    return Ok(Some(event.time));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Wait for the next event until `deadline`.
+    ///
+    /// Returns `None` if `caller` stopped waiting for the result.
+    fn next_event(
+        &self,
+        deadline: Option<Instant>,
+        caller: &mut oneshot::Sender<Conversion>,
+    ) -> Result<Option<Event>, Error> {
+        let mut context = task::Context::from_waker(&self.waker);
+
+        loop {
+            // Also makes sure we are woken up if the caller goes away while
+            // we wait.
+            if caller.poll_canceled(&mut context).is_ready() {
+                return Ok(None);
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::Timeout);
+            }
+
+            match self.context.connection.poll_for_event()? {
+                Some(event) => return Ok(Some(event)),
+                None => self.wait(deadline)?,
+            }
+        }
+    }
+
+    /// Block until the connection has something to read, the reader is
+    /// woken up, or `deadline` passes.
+    fn wait(&self, deadline: Option<Instant>) -> Result<(), Error> {
+        let timeout = deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+            .and_then(|timeout| Timespec::try_from(timeout).ok());
+
+        let mut fds = [
+            PollFd::new(self.context.connection.stream(), PollFlags::IN),
+            PollFd::new(&self.wakeup, PollFlags::IN),
+        ];
+
+        match event::poll(&mut fds, timeout.as_ref()) {
+            Ok(_) | Err(Errno::INTR) => {}
+            Err(error) => return Err(io_error(error)),
+        }
+
+        if !fds[1].revents().is_empty() {
+            let mut buffer = [0; 64];
+
+            while let Ok(1..) = rustix::io::read(&self.wakeup, &mut buffer) {}
+        }
+
+        Ok(())
+    }
+
+    /// The length to request a property with, in 32-bit units, so that we
+    /// learn about values over the maximum size without fetching them.
+    fn max_length(&self) -> u32 {
+        match self.settings.max_transfer_size {
+            Some(max) => (max / 4 + 1).min(u32::MAX as usize) as u32,
+            None => u32::MAX,
+        }
+    }
+
+    /// Fail if a value of `size` bytes exceeds the maximum transfer size.
+    fn check_size(&self, size: usize) -> Result<(), Error> {
+        match self.settings.max_transfer_size {
+            Some(max) if size > max => Err(Error::TooLarge(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn io_error(errno: Errno) -> Error {
+    Error::Io(errno.into())
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+/// The settings of a [`Clipboard`](crate::Clipboard) connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// How long the owner of a selection has to answer a conversion.
+    pub read_timeout: Duration,
+    /// The largest value that will be read, in bytes.
+    ///
+    /// Larger values fail with [`Error::TooLarge`](crate::Error::TooLarge).
+    pub max_transfer_size: Option<usize>,
+    /// The size of the chunks large values are sent in, in bytes.
+    ///
+    /// Chunks are never larger than the largest request the server accepts.
+    pub incr_chunk_size: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            read_timeout: Duration::from_secs(3),
+            max_transfer_size: None,
+            incr_chunk_size: 4000,
+        }
+    }
+}
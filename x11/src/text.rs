@@ -0,0 +1,431 @@
+//! Conversions between strings and the text targets clients exchange.
+use crate::{Atoms, Error};
+
+use x11rb::protocol::xproto::Atom;
+
+/// The text targets, in order of preference when reading.
+pub fn targets(atoms: &Atoms) -> Vec<Atom> {
+    vec![
+        atoms.utf8_string,
+        atoms.text_plain_utf8,
+        atoms.string,
+        atoms.text,
+        atoms.compound_text,
+        atoms.text_plain_utf16,
+        atoms.text_plain,
+    ]
+}
+
+/// Decode text stored in a property of the given type.
+pub fn decode(
+    atoms: &Atoms,
+    kind: Atom,
+    data: Vec<u8>,
+) -> Result<String, Error> {
+    if kind == atoms.string {
+        Ok(decode_latin1(&data))
+    } else if kind == atoms.compound_text {
+        decode_compound_text(&data)
+    } else if kind == atoms.text_plain_utf16 {
+        decode_utf16(&data)
+    } else {
+        String::from_utf8(data).map_err(Error::InvalidUtf8)
+    }
+}
+
+/// Encode text for the given target, returning the type of the property
+/// to store it in along with its data.
+///
+/// Returns `None` if `target` is not a text target.
+pub fn encode(
+    atoms: &Atoms,
+    target: Atom,
+    text: &str,
+) -> Option<(Atom, Vec<u8>)> {
+    let encoded = if target == atoms.string {
+        (target, encode_latin1(text))
+    } else if target == atoms.text {
+        // The owner picks the encoding of `TEXT`, and the ICCCM only allows
+        // the ones below.
+        if text.chars().all(|c| u32::from(c) <= 0xFF) {
+            (atoms.string, encode_latin1(text))
+        } else {
+            (atoms.compound_text, encode_compound_text(text))
+        }
+    } else if target == atoms.compound_text {
+        (target, encode_compound_text(text))
+    } else if target == atoms.text_plain_utf16 {
+        (target, encode_utf16(text))
+    } else if [atoms.utf8_string, atoms.text_plain_utf8, atoms.text_plain]
+        .contains(&target)
+    {
+        (target, text.as_bytes().to_vec())
+    } else {
+        return None;
+    };
+
+    Some(encoded)
+}
+
+fn decode_latin1(data: &[u8]) -> String {
+    data.iter().map(|&byte| char::from(byte)).collect()
+}
+
+/// Characters outside of Latin-1 are replaced with `?`.
+fn encode_latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if u32::from(c) <= 0xFF { c as u8 } else { b'?' })
+        .collect()
+}
+
+/// Decodes UTF-16, big-endian unless a byte order mark says otherwise as
+/// per RFC 2781.
+fn decode_utf16(data: &[u8]) -> Result<String, Error> {
+    let (data, little_endian) = match data {
+        [0xFF, 0xFE, rest @ ..] => (rest, true),
+        [0xFE, 0xFF, rest @ ..] => (rest, false),
+        _ => (data, false),
+    };
+
+    let units = data.chunks_exact(2);
+
+    if !units.remainder().is_empty() {
+        return Err(Error::InvalidText("UTF-16"));
+    }
+
+    let units = units.map(|unit| {
+        if little_endian {
+            u16::from_le_bytes([unit[0], unit[1]])
+        } else {
+            u16::from_be_bytes([unit[0], unit[1]])
+        }
+    });
+
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|_| Error::InvalidText("UTF-16"))
+}
+
+/// Encodes UTF-16 in little-endian, with a byte order mark.
+fn encode_utf16(text: &str) -> Vec<u8> {
+    std::iter::once(0xFEFF)
+        .chain(text.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+const ESC: u8 = 0x1B;
+const CSI: u8 = 0x9B;
+
+/// A character set designated to one half of the COMPOUND_TEXT code table.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    /// The right half of ISO 8859-1.
+    Latin1,
+    /// Any other set, whose characters take the given number of bytes.
+    Unsupported(usize),
+}
+
+/// Decodes COMPOUND_TEXT.
+///
+/// ASCII, Latin-1 and UTF-8 segments are supported. Characters of any other
+/// set are replaced with U+FFFD.
+fn decode_compound_text(data: &[u8]) -> Result<String, Error> {
+    const INVALID: Error = Error::InvalidText("COMPOUND_TEXT");
+
+    let mut text = String::with_capacity(data.len());
+    let mut gl = Charset::Ascii;
+    let mut gr = Charset::Latin1;
+    let mut rest = data;
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+
+        match byte {
+            ESC => match rest {
+                [b'(', b'B', tail @ ..] => {
+                    gl = Charset::Ascii;
+                    rest = tail;
+                }
+                [b'(', _, tail @ ..] => {
+                    gl = Charset::Unsupported(1);
+                    rest = tail;
+                }
+                [b')', b'B', tail @ ..] => {
+                    gr = Charset::Ascii;
+                    rest = tail;
+                }
+                [b'-', b'A', tail @ ..] => {
+                    gr = Charset::Latin1;
+                    rest = tail;
+                }
+                [b')' | b'-', _, tail @ ..] => {
+                    gr = Charset::Unsupported(1);
+                    rest = tail;
+                }
+                [b'$', b'(', _, tail @ ..] => {
+                    gl = Charset::Unsupported(2);
+                    rest = tail;
+                }
+                [b'$', b')', _, tail @ ..] => {
+                    gr = Charset::Unsupported(2);
+                    rest = tail;
+                }
+                // A UTF-8 segment, up to `ESC % @`.
+                [b'%', b'G', tail @ ..] => {
+                    let end = tail
+                        .windows(3)
+                        .position(|window| window == [ESC, b'%', b'@'])
+                        .unwrap_or(tail.len());
+
+                    let segment = std::str::from_utf8(&tail[..end])
+                        .map_err(|_| INVALID)?;
+
+                    text.push_str(segment);
+                    rest = tail.get(end + 3..).unwrap_or_default();
+                }
+                // A segment in a non-standard encoding, prefixed with its
+                // length.
+                [b'%', b'/', _, m, l, tail @ ..] => {
+                    let length =
+                        usize::from(m & 0x7F) * 128 + usize::from(l & 0x7F);
+
+                    rest = tail.get(length..).ok_or(INVALID)?;
+                    text.push(char::REPLACEMENT_CHARACTER);
+                }
+                _ => return Err(INVALID),
+            },
+            // Changes of direction do not affect the text itself.
+            CSI => {
+                let end = rest
+                    .iter()
+                    .position(|&byte| byte == b']')
+                    .ok_or(INVALID)?;
+
+                rest = &rest[end + 1..];
+            }
+            b'\n' | b'\t' | 0x20 => text.push(char::from(byte)),
+            // Latin-1 has 96 characters, so it is only ever designated to the
+            // right half.
+            0x21..=0x7E => match gl {
+                Charset::Ascii | Charset::Latin1 => text.push(char::from(byte)),
+                Charset::Unsupported(width) => {
+                    rest = rest.get(width - 1..).ok_or(INVALID)?;
+                    text.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            0xA0..=0xFF => match gr {
+                Charset::Latin1 => text.push(char::from(byte)),
+                Charset::Ascii => text.push(char::from(byte & 0x7F)),
+                Charset::Unsupported(width) => {
+                    rest = rest.get(width - 1..).ok_or(INVALID)?;
+                    text.push(char::REPLACEMENT_CHARACTER);
+                }
+            },
+            // Other control characters are not allowed, and skipped.
+            _ => {}
+        }
+    }
+
+    Ok(text)
+}
+
+/// Encodes COMPOUND_TEXT, using UTF-8 segments for characters outside of
+/// Latin-1.
+fn encode_compound_text(text: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(text.len());
+    let mut in_utf8 = false;
+
+    for c in text.chars() {
+        let code = u32::from(c);
+
+        let is_latin1 = c == '\n'
+            || c == '\t'
+            || (0x20..0x7F).contains(&code)
+            || (0xA0..=0xFF).contains(&code);
+
+        if is_latin1 {
+            if in_utf8 {
+                data.extend_from_slice(&[ESC, b'%', b'@']);
+                in_utf8 = false;
+            }
+
+            data.push(code as u8);
+        } else if !c.is_control() {
+            if !in_utf8 {
+                data.extend_from_slice(&[ESC, b'%', b'G']);
+                in_utf8 = true;
+            }
+
+            data.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    }
+
+    if in_utf8 {
+        data.extend_from_slice(&[ESC, b'%', b'@']);
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atoms() -> Atoms {
+        Atoms {
+            primary: 1,
+            secondary: 2,
+            clipboard: 3,
+            property: 4,
+            targets: 5,
+            length: 6,
+            multiple: 7,
+            timestamp: 8,
+            string: 9,
+            utf8_string: 10,
+            incr: 11,
+            clipboard_manager: 12,
+            save_targets: 13,
+            text: 14,
+            compound_text: 15,
+            text_plain: 16,
+            text_plain_utf8: 17,
+            text_plain_utf16: 18,
+        }
+    }
+
+    fn is_invalid(result: Result<String, Error>) -> bool {
+        matches!(result, Err(Error::InvalidText(_)))
+    }
+
+    #[test]
+    fn compound_text_round_trip() {
+        for text in [
+            "",
+            "plain ASCII\twith\ncontrols",
+            "Latin-1: café, naïve, ¡olé!",
+            "mixed: café → 日本語 and back to ASCII ü",
+            "→ starts and ends outside of Latin-1 ←",
+            "emoji 🦀 between é",
+        ] {
+            let encoded = encode_compound_text(text);
+
+            assert_eq!(decode_compound_text(&encoded).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn compound_text_uses_utf8_segments_outside_of_latin1() {
+        assert_eq!(encode_compound_text("é"), [0xE9]);
+        assert_eq!(
+            encode_compound_text("a→b"),
+            [b'a', ESC, b'%', b'G', 0xE2, 0x86, 0x92, ESC, b'%', b'@', b'b']
+        );
+    }
+
+    #[test]
+    fn compound_text_charsets() {
+        // ASCII designated to the left half, and Latin-1 to the right one.
+        let data = [ESC, b'(', b'B', b'a', ESC, b'-', b'A', 0xE9];
+        assert_eq!(decode_compound_text(&data).unwrap(), "aé");
+
+        // ASCII designated to the right half.
+        let data = [ESC, b')', b'B', 0xE1, ESC, b'-', b'A', 0xE1];
+        assert_eq!(decode_compound_text(&data).unwrap(), "aá");
+
+        // A one-byte set other than ASCII, designated to the left half.
+        let data = [ESC, b'(', b'J', b'a', ESC, b'(', b'B', b'a'];
+        assert_eq!(decode_compound_text(&data).unwrap(), "\u{FFFD}a");
+
+        // A two-byte set, like JIS X 0208, is replaced.
+        let data = [ESC, b'$', b'(', b'B', 0x30, 0x21, ESC, b'(', b'B', b'a'];
+        assert_eq!(decode_compound_text(&data).unwrap(), "\u{FFFD}a");
+
+        // Direction changes are skipped.
+        let data = [CSI, b'1', b']', b'a', CSI, b']'];
+        assert_eq!(decode_compound_text(&data).unwrap(), "a");
+    }
+
+    #[test]
+    fn malformed_compound_text_is_invalid() {
+        for data in [
+            &[ESC][..],
+            &[ESC, b'('],
+            &[ESC, b'x', b'y'],
+            &[ESC, b'$', b'('],
+            &[ESC, b'%', b'/', b'1', 0x80, 0x85, b'a'],
+            &[ESC, b'%', b'G', 0xFF, ESC, b'%', b'@'],
+            &[ESC, b'$', b'(', b'B', 0x30],
+            &[CSI, b'1'],
+            &[CSI],
+        ] {
+            assert!(is_invalid(decode_compound_text(data)), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn utf16_byte_order() {
+        let text = "a€𝄞";
+
+        let little: Vec<u8> = std::iter::once(0xFEFF)
+            .chain(text.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(decode_utf16(&little).unwrap(), text);
+
+        let big: Vec<u8> = std::iter::once(0xFEFF)
+            .chain(text.encode_utf16())
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(decode_utf16(&big).unwrap(), text);
+
+        // Big-endian is assumed without a byte order mark.
+        assert_eq!(decode_utf16(&big[2..]).unwrap(), text);
+
+        assert_eq!(decode_utf16(&encode_utf16(text)).unwrap(), text);
+    }
+
+    #[test]
+    fn malformed_utf16_is_invalid() {
+        assert!(is_invalid(decode_utf16(&[0x00, 0x61, 0x00])));
+        assert!(is_invalid(decode_utf16(&[0xFF, 0xFE, 0x61])));
+        // An unpaired surrogate.
+        assert!(is_invalid(decode_utf16(&[0xD8, 0x00])));
+    }
+
+    #[test]
+    fn latin1() {
+        assert_eq!(decode_latin1(&[b'a', 0xE9, 0xFF]), "aéÿ");
+        assert_eq!(encode_latin1("aé→"), [b'a', 0xE9, b'?']);
+    }
+
+    #[test]
+    fn text_target_picks_an_encoding() {
+        let atoms = atoms();
+
+        assert_eq!(
+            encode(&atoms, atoms.text, "café"),
+            Some((atoms.string, vec![b'c', b'a', b'f', 0xE9]))
+        );
+
+        let (kind, data) = encode(&atoms, atoms.text, "café →").unwrap();
+        assert_eq!(kind, atoms.compound_text);
+        assert_eq!(decode(&atoms, kind, data).unwrap(), "café →");
+
+        assert_eq!(encode(&atoms, atoms.targets, "text"), None);
+    }
+
+    #[test]
+    fn every_target_round_trips() {
+        let atoms = atoms();
+        let text = "héllo";
+
+        for target in targets(&atoms) {
+            let (kind, data) = encode(&atoms, target, text).unwrap();
+
+            assert_eq!(decode(&atoms, kind, data).unwrap(), text);
+        }
+    }
+}
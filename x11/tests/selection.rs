@@ -0,0 +1,169 @@
+use clipboard_testing::{Requestor, SelectionOwner, XServer};
+use clipboard_x11::{Clipboard, Error, Settings};
+
+use x11rb::connection::RequestConnection as _;
+
+use std::time::Duration;
+
+/// Start a private X server.
+///
+/// These tests are ignored by default, as they need Xvfb. Run them with
+/// `cargo test -- --ignored` where it is installed.
+fn server() -> XServer {
+    XServer::start().expect("Xvfb is needed to run this test")
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn reads_a_foreign_owner() {
+    let server = server();
+
+    let owner = SelectionOwner::new("CLIPBOARD")
+        .target("UTF8_STRING", "Hello")
+        .target("text/html", "<b>Hello</b>")
+        .spawn(server.display())
+        .unwrap();
+
+    let clipboard = Clipboard::connect_to(server.display()).unwrap();
+
+    let targets: Vec<String> = clipboard
+        .available_formats()
+        .unwrap()
+        .into_iter()
+        .map(|(target, _)| target)
+        .collect();
+
+    assert_eq!(targets, ["UTF8_STRING", "text/html"]);
+    assert_eq!(clipboard.read().unwrap(), "Hello");
+    assert_eq!(
+        clipboard.read_mime(&["image/png", "text/html"]).unwrap(),
+        ("text/html".to_owned(), b"<b>Hello</b>".to_vec())
+    );
+
+    assert!(owner.requests().iter().any(|target| target == "TARGETS"));
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn reads_a_foreign_owner_incrementally() {
+    let server = server();
+
+    let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+
+    let _owner = SelectionOwner::new("CLIPBOARD")
+        .target("image/png", data.clone())
+        .incr(4096)
+        .spawn(server.display())
+        .unwrap();
+
+    let clipboard = Clipboard::connect_to(server.display()).unwrap();
+
+    assert_eq!(
+        clipboard.read_mime(&["image/png"]).unwrap(),
+        ("image/png".to_owned(), data)
+    );
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn times_out_on_an_unresponsive_owner() {
+    let server = server();
+
+    let owner = SelectionOwner::new("CLIPBOARD")
+        .target("UTF8_STRING", "Hello")
+        .unresponsive()
+        .spawn(server.display())
+        .unwrap();
+
+    let clipboard = Clipboard::connect_to_with(
+        server.display(),
+        Settings {
+            read_timeout: Duration::from_millis(200),
+            ..Settings::default()
+        },
+    )
+    .unwrap();
+
+    assert!(matches!(clipboard.read(), Err(Error::Timeout)));
+    assert!(!owner.requests().is_empty());
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn answers_targets_and_multiple() {
+    let server = server();
+
+    let mut clipboard = Clipboard::connect_to(server.display()).unwrap();
+
+    clipboard
+        .write_mime(vec![
+            ("text/plain".to_owned(), b"Hello".to_vec()),
+            ("text/html".to_owned(), b"<b>Hello</b>".to_vec()),
+        ])
+        .unwrap();
+
+    let requestor = Requestor::connect(server.display()).unwrap();
+    let targets = requestor.targets("CLIPBOARD").unwrap().unwrap();
+
+    for target in &[
+        "TARGETS",
+        "MULTIPLE",
+        "TIMESTAMP",
+        "text/plain",
+        "text/html",
+    ] {
+        assert!(targets.iter().any(|name| name == target), "{}", target);
+    }
+
+    let values = requestor
+        .convert_multiple(
+            "CLIPBOARD",
+            &["text/html", "image/png", "text/plain"],
+        )
+        .unwrap()
+        .unwrap();
+
+    let data: Vec<Option<&[u8]>> = values
+        .iter()
+        .map(|value| value.as_ref().map(|value| value.data.as_slice()))
+        .collect();
+
+    assert_eq!(
+        data,
+        [Some(&b"<b>Hello</b>"[..]), None, Some(&b"Hello"[..])]
+    );
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn sends_values_larger_than_a_request_incrementally() {
+    let server = server();
+
+    let (connection, _) = x11rb::connect(Some(server.display())).unwrap();
+    let size = connection.maximum_request_bytes() + 1;
+
+    let mut clipboard = Clipboard::connect_to_with(
+        server.display(),
+        Settings {
+            incr_chunk_size: 1 << 20,
+            ..Settings::default()
+        },
+    )
+    .unwrap();
+
+    let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+
+    clipboard
+        .write_mime(vec![("image/png".to_owned(), data.clone())])
+        .unwrap();
+
+    let requestor = Requestor::connect(server.display()).unwrap();
+    let value = requestor
+        .convert("CLIPBOARD", "image/png")
+        .unwrap()
+        .unwrap();
+
+    assert!(value.is_incr);
+    assert_eq!(value.kind, "image/png");
+    assert!(value.data == data);
+}